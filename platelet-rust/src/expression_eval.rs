@@ -12,6 +12,10 @@ pub enum EvalError {
     // Undefined(String),
 }
 
+/// The key under which a component's context lists the slots its caller filled.
+/// It isn't a valid identifier, so templates can only read it through `has_slot`.
+pub(crate) const SLOTS_KEY: &str = "$slots";
+
 pub(crate) fn eval(exp: &Expression, vars: &Value) -> Result<Value, EvalError> {
     match exp {
         Expression::Indexed(indexed_exp) => {
@@ -187,6 +191,18 @@ pub(crate) fn eval(exp: &Expression, vars: &Value) -> Result<Value, EvalError> {
                     Value::Object(o) => Ok(o.len().into()),
                     _ => Err(EvalError::TypeMismatch),
                 },
+                "str" => match eval(arg, vars)? {
+                    Value::String(s) => Ok(Value::String(s)),
+                    v => Ok(Value::String(v.to_string())),
+                },
+                "has_slot" => match eval(arg, vars)? {
+                    Value::String(s) => Ok(vars
+                        .get(SLOTS_KEY)
+                        .and_then(|slots| slots.get(s))
+                        .is_some()
+                        .into()),
+                    _ => Err(EvalError::TypeMismatch),
+                },
                 _ => Err(EvalError::UndefinedFunction(id.clone())),
             }
         }
@@ -225,7 +241,7 @@ pub(crate) fn eval(exp: &Expression, vars: &Value) -> Result<Value, EvalError> {
     }
 }
 
-fn are_equal(a: &Value, b: &Value) -> bool {
+pub(crate) fn are_equal(a: &Value, b: &Value) -> bool {
     match (a, b) {
         (Value::Number(a), Value::Number(b)) => {
             if a.is_f64() && b.is_f64() {
@@ -520,6 +536,22 @@ mod test {
         assert_eq!(eval(&exp, &vars), Ok(3.into()));
     }
 
+    #[test]
+    fn to_string() {
+        let vars = Map::new().into();
+        let mut exp = "str(false) + str(1.5) + str('x') + str(null)";
+        let exp = expr(&mut exp).unwrap();
+        assert_eq!(eval(&exp, &vars), Ok("false1.5xnull".into()));
+    }
+
+    #[test]
+    fn has_slot() {
+        let vars = json!({ "$slots": { "footer": true } });
+        let mut exp = "[has_slot('footer'), has_slot('header')]";
+        let exp = expr(&mut exp).unwrap();
+        assert_eq!(eval(&exp, &vars), Ok(json!([true, false])));
+    }
+
     #[test]
     fn str_length() {
         let vars = Map::new().into();
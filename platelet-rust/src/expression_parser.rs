@@ -36,6 +36,27 @@ pub(crate) enum Expression {
     Identifier(String),
 }
 
+impl Expression {
+    /// Whether evaluating the expression reads the variable `name`
+    pub(crate) fn references(&self, name: &str) -> bool {
+        match self {
+            Expression::Identifier(id) => id == name,
+            Expression::Indexed(e) => e.0.references(name) || e.1.references(name),
+            Expression::BinaryOperation(e) => e.0.references(name) || e.2.references(name),
+            Expression::FunctionCall(e) => e.1.references(name),
+            Expression::UnaryOperation(e) => e.1.references(name),
+            Expression::Conditional(e) => {
+                e.0.references(name) || e.1.references(name) || e.2.references(name)
+            }
+            Expression::Array(xs) => xs.iter().any(|x| x.references(name)),
+            Expression::Object(o) => o.values().any(|x| x.references(name)),
+            Expression::Null | Expression::Boolean(_) | Expression::Str(_) | Expression::Num(_) => {
+                false
+            }
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub(crate) enum BinaryOperator {
     Add,
@@ -309,7 +330,11 @@ fn single_string(input: &mut &str) -> PResult<String> {
 }
 
 pub(crate) fn identifier<'s>(input: &'s mut &str) -> PResult<String> {
-    take_while(1.., ('a'..='z', 'A'..='Z', '_'))
+    (
+        take_while(1.., ('a'..='z', 'A'..='Z', '_')),
+        take_while(0.., ('a'..='z', 'A'..='Z', '0'..='9', '_')),
+    )
+        .recognize()
         .parse_next(input)
         .map(|s| s.to_string())
 }
@@ -401,7 +426,7 @@ pub(crate) fn ws<'s>(input: &mut &'s str) -> PResult<&'s str> {
     take_while(0.., WS).parse_next(input)
 }
 
-const WS: &[char] = &[' ', '\t', '\r', '\n'];
+pub(crate) const WS: &[char] = &[' ', '\t', '\r', '\n'];
 
 #[cfg(test)]
 mod test {
@@ -553,6 +578,15 @@ mod test {
         assert_eq!(identifier.parse_peek(input), Ok(("", "window".to_owned())))
     }
 
+    #[test]
+    fn identifier_with_digits() {
+        assert_eq!(
+            identifier.parse_peek("index1"),
+            Ok(("", "index1".to_owned()))
+        );
+        assert!(identifier.parse_peek("1index").is_err());
+    }
+
     #[test]
     fn expression_multi_identifier() {
         let input = r#"props.user.name"#;
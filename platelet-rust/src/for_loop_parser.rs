@@ -1,20 +1,69 @@
-use winnow::combinator::alt;
-use winnow::combinator::{delimited, separated_pair};
-use winnow::error::StrContext;
+use winnow::combinator::delimited;
+use winnow::combinator::{alt, cut_err, opt, preceded, separated, terminated};
+use winnow::error::{StrContext, StrContextValue};
 use winnow::prelude::*;
+use winnow::token::take_while;
 
 use crate::expression_parser::Expression;
-use crate::expression_parser::{expression, identifier, ws};
+use crate::expression_parser::{expression, identifier, ws, WS};
 
 #[derive(Debug, PartialEq)]
-pub(crate) enum ForLoop {
+pub(crate) struct ForLoop {
+    pub(crate) bindings: Bindings,
+    pub(crate) iterable: Expression,
+    // item in items if item.visible
+    pub(crate) guard: Option<Expression>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Bindings {
     // item in items
-    Simple(String, Expression),
+    Simple(Pattern),
     // (item, index) in items
-    // (value, key) in object
-    IndexedObjectOrKeyValue((String, String), Expression),
-    // (value, name, index) in object
-    IndexedKeyValue((String, String, String), Expression),
+    // (key, value) in object
+    IndexedObjectOrKeyValue(Pattern, Pattern),
+    // (key, value, index) in object
+    IndexedKeyValue(Pattern, Pattern, Pattern),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Pattern {
+    // item
+    Identifier(String),
+    // { name, price: cost }
+    Object(Vec<(String, Pattern)>),
+    // [a, b]
+    Array(Vec<Pattern>),
+}
+
+impl ForLoop {
+    /// The variables set in each iteration, including `loop`
+    pub(crate) fn bound_names(&self) -> Vec<&str> {
+        let mut names = vec!["loop"];
+        match &self.bindings {
+            Bindings::Simple(a) => a.names(&mut names),
+            Bindings::IndexedObjectOrKeyValue(a, b) => {
+                a.names(&mut names);
+                b.names(&mut names);
+            }
+            Bindings::IndexedKeyValue(a, b, c) => {
+                a.names(&mut names);
+                b.names(&mut names);
+                c.names(&mut names);
+            }
+        }
+        names
+    }
+}
+
+impl Pattern {
+    fn names<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Pattern::Identifier(id) => out.push(id),
+            Pattern::Object(fields) => fields.iter().for_each(|(_, p)| p.names(out)),
+            Pattern::Array(items) => items.iter().for_each(|p| p.names(out)),
+        }
+    }
 }
 
 pub(crate) fn for_loop(input: &mut &str) -> Result<ForLoop, String> {
@@ -23,39 +72,102 @@ pub(crate) fn for_loop(input: &mut &str) -> Result<ForLoop, String> {
         .map_err(|e| format!("{}", e.to_string()))
 }
 
+pub(crate) fn destructuring_pattern(input: &mut &str) -> Result<Pattern, String> {
+    delimited(ws, pattern, ws)
+        .parse(input)
+        .map_err(|e| e.to_string())
+}
+
 fn for_(input: &mut &str) -> PResult<ForLoop> {
     alt((
-        separated_pair(identifier, (ws, "in", ws), expression)
-            .map(|(id, exp)| ForLoop::Simple(id, exp)),
-        separated_pair(
-            delimited(
-                ('(', ws),
-                separated_pair(identifier, (ws, ',', ws), identifier),
-                (ws, ')'),
-            ),
-            (ws, "in", ws),
-            expression,
-        )
-        .map(|(p, exp)| ForLoop::IndexedObjectOrKeyValue(p, exp)),
-        separated_pair(
-            delimited(
-                ('(', ws),
-                separated_pair(
-                    identifier,
-                    (ws, ',', ws),
-                    separated_pair(identifier, (ws, ',', ws), identifier),
-                ),
-                (ws, ')'),
-            ),
-            (ws, "in", ws),
-            expression,
-        )
-        .map(|((a, (b, c)), exp)| ForLoop::IndexedKeyValue((a, b, c), exp)),
+        (pattern.map(Bindings::Simple), iterable, opt(guard)),
+        (parenthesized_bindings, iterable, opt(guard)),
     ))
+    .map(|(bindings, iterable, guard)| ForLoop {
+        bindings,
+        iterable,
+        guard,
+    })
     .context(StrContext::Label("for loop"))
     .parse_next(input)
 }
 
+fn parenthesized_bindings(input: &mut &str) -> PResult<Bindings> {
+    delimited(
+        ('(', ws),
+        separated(1..=3, pattern, (ws, ',', ws)),
+        (ws, ')'),
+    )
+    .map(|patterns: Vec<Pattern>| {
+        let mut patterns = patterns.into_iter();
+        match (patterns.next(), patterns.next(), patterns.next()) {
+            (Some(a), Some(b), Some(c)) => Bindings::IndexedKeyValue(a, b, c),
+            (Some(a), Some(b), None) => Bindings::IndexedObjectOrKeyValue(a, b),
+            (Some(a), _, _) => Bindings::Simple(a),
+            (None, _, _) => unreachable!("separated parses at least one pattern"),
+        }
+    })
+    .parse_next(input)
+}
+
+fn iterable(input: &mut &str) -> PResult<Expression> {
+    preceded((ws, "in", ws), expression).parse_next(input)
+}
+
+fn guard(input: &mut &str) -> PResult<Expression> {
+    preceded(
+        (ws, "if"),
+        cut_err(preceded(take_while(1.., WS), expression))
+            .context(StrContext::Label("for loop guard"))
+            .context(StrContext::Expected(StrContextValue::Description(
+                "an expression after `if`",
+            ))),
+    )
+    .parse_next(input)
+}
+
+fn pattern(input: &mut &str) -> PResult<Pattern> {
+    alt((
+        identifier.map(Pattern::Identifier),
+        object_pattern.map(Pattern::Object),
+        array_pattern.map(Pattern::Array),
+    ))
+    .parse_next(input)
+}
+
+fn object_pattern(input: &mut &str) -> PResult<Vec<(String, Pattern)>> {
+    preceded(
+        ('{', ws),
+        cut_err(terminated(
+            separated(1.., field_pattern, (ws, ',', ws)),
+            (ws, '}'),
+        ))
+        .context(StrContext::Label("destructuring pattern")),
+    )
+    .parse_next(input)
+}
+
+fn field_pattern(input: &mut &str) -> PResult<(String, Pattern)> {
+    (identifier, opt(preceded((ws, ':', ws), pattern)))
+        .map(|(key, pattern)| {
+            let pattern = pattern.unwrap_or_else(|| Pattern::Identifier(key.clone()));
+            (key, pattern)
+        })
+        .parse_next(input)
+}
+
+fn array_pattern(input: &mut &str) -> PResult<Vec<Pattern>> {
+    preceded(
+        ('[', ws),
+        cut_err(terminated(
+            separated(1.., pattern, (ws, ',', ws)),
+            (ws, ']'),
+        ))
+        .context(StrContext::Label("destructuring pattern")),
+    )
+    .parse_next(input)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -66,10 +178,11 @@ mod test {
             for_.parse_peek("item in items"),
             Ok((
                 "",
-                ForLoop::Simple(
-                    "item".to_owned(),
-                    Expression::Identifier("items".to_owned())
-                )
+                ForLoop {
+                    bindings: Bindings::Simple(Pattern::Identifier("item".to_owned())),
+                    iterable: Expression::Identifier("items".to_owned()),
+                    guard: None,
+                }
             ))
         );
     }
@@ -80,10 +193,14 @@ mod test {
             for_.parse_peek("(item, i) in items"),
             Ok((
                 "",
-                ForLoop::IndexedObjectOrKeyValue(
-                    ("item".to_owned(), "i".to_owned()),
-                    Expression::Identifier("items".to_owned())
-                )
+                ForLoop {
+                    bindings: Bindings::IndexedObjectOrKeyValue(
+                        Pattern::Identifier("item".to_owned()),
+                        Pattern::Identifier("i".to_owned())
+                    ),
+                    iterable: Expression::Identifier("items".to_owned()),
+                    guard: None,
+                }
             ))
         );
     }
@@ -94,11 +211,77 @@ mod test {
             for_.parse_peek("(key, value, index) in items"),
             Ok((
                 "",
-                ForLoop::IndexedKeyValue(
-                    ("key".to_owned(), "value".to_owned(), "index".to_owned()),
-                    Expression::Identifier("items".to_owned())
-                )
+                ForLoop {
+                    bindings: Bindings::IndexedKeyValue(
+                        Pattern::Identifier("key".to_owned()),
+                        Pattern::Identifier("value".to_owned()),
+                        Pattern::Identifier("index".to_owned())
+                    ),
+                    iterable: Expression::Identifier("items".to_owned()),
+                    guard: None,
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn guard() {
+        assert_eq!(
+            for_.parse_peek("p in products if p.in_stock"),
+            Ok((
+                "",
+                ForLoop {
+                    bindings: Bindings::Simple(Pattern::Identifier("p".to_owned())),
+                    iterable: Expression::Identifier("products".to_owned()),
+                    guard: Some(Expression::Indexed(Box::new((
+                        Expression::Identifier("p".to_owned()),
+                        Expression::Str("in_stock".to_owned())
+                    )))),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn destructuring() {
+        assert_eq!(
+            for_.parse_peek("({ name, price: cost }, [a, b]) in products"),
+            Ok((
+                "",
+                ForLoop {
+                    bindings: Bindings::IndexedObjectOrKeyValue(
+                        Pattern::Object(vec![
+                            ("name".to_owned(), Pattern::Identifier("name".to_owned())),
+                            ("price".to_owned(), Pattern::Identifier("cost".to_owned())),
+                        ]),
+                        Pattern::Array(vec![
+                            Pattern::Identifier("a".to_owned()),
+                            Pattern::Identifier("b".to_owned()),
+                        ])
+                    ),
+                    iterable: Expression::Identifier("products".to_owned()),
+                    guard: None,
+                }
             ))
         );
     }
+
+    #[test]
+    fn malformed_pattern() {
+        assert_eq!(
+            for_loop(&mut "({ name, }) in products"),
+            Err("({ name, }) in products\n       ^\ninvalid destructuring pattern".to_owned())
+        );
+    }
+
+    #[test]
+    fn missing_guard() {
+        assert_eq!(
+            for_loop(&mut "item in items if"),
+            Err(
+                "item in items if\n                ^\ninvalid for loop guard\nexpected an expression after `if`"
+                    .to_owned()
+            )
+        );
+    }
 }
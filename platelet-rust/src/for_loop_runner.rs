@@ -1,11 +1,15 @@
-use serde_json::Value;
+use serde_json::{json, Value};
 
 use crate::{
-    expression_eval::{eval, EvalError},
-    for_loop_parser::ForLoop,
+    expression_eval::{eval, truthy, EvalError},
+    for_loop_parser::{Bindings, ForLoop, Pattern},
     types::{type_of, Type},
 };
 
+/// The key under which the context keeps the metadata of the innermost loop, even when
+/// `loop` is a variable of the same name. It isn't a valid identifier, so templates can't read it.
+const LOOP_KEY: &str = "$loop";
+
 #[derive(Debug, PartialEq)]
 pub enum Error {
     TypeMismatch { expected: Vec<Type>, found: Type },
@@ -16,80 +20,161 @@ pub(crate) fn for_loop_runner(
     for_loop: &ForLoop,
     base_context: &Value,
 ) -> Result<Vec<Value>, Error> {
-    match for_loop {
-        ForLoop::Simple(id, exp) => {
-            let val = eval(exp, base_context).map_err(Error::Eval)?;
-            match val {
-                Value::Array(vec) => Ok(vec
-                    .iter()
-                    .map(|v| {
-                        let mut obj = base_context.as_object().unwrap().clone();
-                        obj.insert(id.clone(), v.clone());
-                        Value::Object(obj)
-                    })
-                    .collect()),
-                _ => Err(Error::TypeMismatch {
+    let val = eval(&for_loop.iterable, base_context).map_err(Error::Eval)?;
+
+    let mut iterations = vec![];
+
+    for bindings in bindings(&for_loop.bindings, val)? {
+        if let Some(guard) = &for_loop.guard {
+            let mut obj = base_context.as_object().unwrap().clone();
+            for (id, v) in &bindings {
+                obj.insert(id.clone(), v.clone());
+            }
+            let keep = eval(guard, &Value::Object(obj)).map_err(Error::Eval)?;
+            if !truthy(&keep) {
+                continue;
+            }
+        }
+        iterations.push(bindings);
+    }
+
+    let length = iterations.len();
+    let parent = base_context.get(LOOP_KEY).cloned().unwrap_or(Value::Null);
+
+    // a `loop` that isn't from an enclosing `pl-for` was passed in, so it's left alone
+    let shadow_loop = match base_context.get("loop") {
+        None => true,
+        Some(existing) => base_context.get(LOOP_KEY) == Some(existing),
+    };
+
+    Ok(iterations
+        .into_iter()
+        .enumerate()
+        .map(|(index, bindings)| {
+            let mut obj = base_context.as_object().unwrap().clone();
+            let metadata = loop_metadata(index, length, &parent);
+            if shadow_loop {
+                obj.insert("loop".to_owned(), metadata.clone());
+            }
+            obj.insert(LOOP_KEY.to_owned(), metadata);
+            for (id, v) in bindings {
+                obj.insert(id, v);
+            }
+            Value::Object(obj)
+        })
+        .collect())
+}
+
+fn loop_metadata(index: usize, length: usize, parent: &Value) -> Value {
+    json!({
+        "index": index,
+        "index1": index + 1,
+        "first": index == 0,
+        "last": index + 1 == length,
+        "length": length,
+        "even": index.is_multiple_of(2),
+        "odd": !index.is_multiple_of(2),
+        "parent": parent,
+    })
+}
+
+fn bindings(bindings: &Bindings, val: Value) -> Result<Vec<Vec<(String, Value)>>, Error> {
+    let mut iterations = vec![];
+
+    match bindings {
+        Bindings::Simple(pattern) => match val {
+            Value::Array(vec) => {
+                for v in vec {
+                    let mut out = vec![];
+                    bind(pattern, v, &mut out)?;
+                    iterations.push(out);
+                }
+            }
+            _ => {
+                return Err(Error::TypeMismatch {
                     expected: vec![Type::Array],
                     found: type_of(&val),
-                }),
+                })
             }
-        }
-        ForLoop::IndexedObjectOrKeyValue(ids, exp) => {
-            let val = eval(exp, base_context).map_err(Error::Eval)?;
-            match val {
-                Value::Array(vec) => {
-                    let (id, indexer) = ids;
-                    Ok(vec
-                        .iter()
-                        .enumerate()
-                        .map(|(index, v)| {
-                            let mut obj = base_context.as_object().unwrap().clone();
-                            obj.insert(id.clone(), v.clone());
-                            obj.insert(indexer.clone(), index.clone().into());
-                            Value::Object(obj)
-                        })
-                        .collect())
+        },
+        Bindings::IndexedObjectOrKeyValue(a, b) => match val {
+            Value::Array(vec) => {
+                let (item, indexer) = (a, b);
+                for (index, v) in vec.into_iter().enumerate() {
+                    let mut out = vec![];
+                    bind(item, v, &mut out)?;
+                    bind(indexer, index.into(), &mut out)?;
+                    iterations.push(out);
                 }
-                Value::Object(vec) => {
-                    let (key_id, value_id) = ids;
-                    Ok(vec
-                        .iter()
-                        .map(|(k, v)| {
-                            let mut obj = base_context.as_object().unwrap().clone();
-                            obj.insert(key_id.clone(), k.clone().into());
-                            obj.insert(value_id.clone(), v.clone());
-                            Value::Object(obj)
-                        })
-                        .collect())
+            }
+            Value::Object(obj) => {
+                let (key, value) = (a, b);
+                for (k, v) in obj {
+                    let mut out = vec![];
+                    bind(key, k.into(), &mut out)?;
+                    bind(value, v, &mut out)?;
+                    iterations.push(out);
                 }
-                _ => Err(Error::TypeMismatch {
+            }
+            _ => {
+                return Err(Error::TypeMismatch {
                     expected: vec![Type::Array, Type::Object],
                     found: type_of(&val),
-                }),
+                })
             }
-        }
-        ForLoop::IndexedKeyValue(ids, exp) => {
-            let val = eval(exp, base_context).map_err(Error::Eval)?;
-            match val {
-                Value::Object(vec) => {
-                    let (key_id, value_id, indexer) = ids;
-                    Ok(vec
-                        .iter()
-                        .enumerate()
-                        .map(|(index, (k, v))| {
-                            let mut obj = base_context.as_object().unwrap().clone();
-                            obj.insert(key_id.clone(), k.clone().into());
-                            obj.insert(value_id.clone(), v.clone());
-                            obj.insert(indexer.clone(), index.clone().into());
-                            Value::Object(obj)
-                        })
-                        .collect())
+        },
+        Bindings::IndexedKeyValue(key, value, indexer) => match val {
+            Value::Object(obj) => {
+                for (index, (k, v)) in obj.into_iter().enumerate() {
+                    let mut out = vec![];
+                    bind(key, k.into(), &mut out)?;
+                    bind(value, v, &mut out)?;
+                    bind(indexer, index.into(), &mut out)?;
+                    iterations.push(out);
                 }
-                _ => Err(Error::TypeMismatch {
+            }
+            _ => {
+                return Err(Error::TypeMismatch {
                     expected: vec![Type::Object],
                     found: type_of(&val),
-                }),
+                })
+            }
+        },
+    }
+
+    Ok(iterations)
+}
+
+pub(crate) fn bind(
+    pattern: &Pattern,
+    val: Value,
+    out: &mut Vec<(String, Value)>,
+) -> Result<(), Error> {
+    match (pattern, val) {
+        (Pattern::Identifier(id), val) => out.push((id.clone(), val)),
+        (Pattern::Object(fields), Value::Object(mut obj)) => {
+            for (key, pattern) in fields {
+                bind(pattern, obj.remove(key).unwrap_or(Value::Null), out)?;
             }
         }
+        (Pattern::Array(items), Value::Array(vec)) => {
+            let mut vec = vec.into_iter();
+            for pattern in items {
+                bind(pattern, vec.next().unwrap_or(Value::Null), out)?;
+            }
+        }
+        (Pattern::Object(_), val) => {
+            return Err(Error::TypeMismatch {
+                expected: vec![Type::Object],
+                found: type_of(&val),
+            })
+        }
+        (Pattern::Array(_), val) => {
+            return Err(Error::TypeMismatch {
+                expected: vec![Type::Array],
+                found: type_of(&val),
+            })
+        }
     }
+    Ok(())
 }
@@ -1,5 +1,7 @@
 use std::fmt::Write as _;
 
+use crate::renderer::{is_boolean_attribute, ORIGIN_TAG};
+
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum Node {
     Text {
@@ -67,7 +69,7 @@ fn push_node_as_string(s: &mut String, n: &Node) {
             attrs,
             children,
         } => {
-            if name == "pl-template" {
+            if name == "pl-template" || name == ORIGIN_TAG {
                 for child in children {
                     push_node_as_string(s, child);
                 }
@@ -79,6 +81,9 @@ fn push_node_as_string(s: &mut String, n: &Node) {
             for (key, value) in attrs {
                 s.push(' ');
                 s.push_str(key);
+                if value.is_empty() && is_boolean_attribute(key) {
+                    continue;
+                }
                 s.push('=');
                 push_attr_value(s, value);
             }
@@ -2,14 +2,14 @@
 
 use core::fmt;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashMap,
     fs::File,
     io::{self, Read},
     path::{Path, PathBuf},
     rc::Rc,
 };
 
-use renderer::{Filesystem, RenderError};
+use renderer::{Filesystem, RenderError, RenderErrorKind, RenderOptions, RenderState};
 use serde_json::Value;
 
 mod expression_eval;
@@ -39,14 +39,42 @@ where
     F: Filesystem<FilesystemError>,
     FilesystemError: fmt::Debug,
 {
+    render_with_options(filename, vars, filesystem, &RenderOptions::default())
+}
+
+/// Like [`render_with_custom_filesystem`](fn@render_with_custom_filesystem), but with [`RenderOptions`](struct@RenderOptions)
+///
+/// * `filename`: Path to a platelet template file
+/// * `context`: Variables to render the template with
+/// * `filesystem`: A struct implementing [`Filesystem`](trait@Filesystem), such as [`PathFilesystem`](struct@PathFilesystem)
+/// * `options`: Options that change how the template is rendered
+pub fn render_with_options<F, FilesystemError>(
+    filename: &String,
+    vars: &Value,
+    filesystem: &F,
+    options: &RenderOptions,
+) -> Result<String, RenderError<FilesystemError>>
+where
+    F: Filesystem<FilesystemError>,
+    FilesystemError: fmt::Debug,
+{
+    if let Some(name) = options.components.keys().find(|name| !name.contains('-')) {
+        return Err(RenderError {
+            kind: RenderErrorKind::BadComponentName(name.clone()),
+            filename: filename.to_owned(),
+        });
+    }
+
     renderer::render(
         vars,
         Rc::new(HashMap::new()),
-        &mut HashSet::new(),
+        &mut RenderState::new(filename, vars),
         &filename,
+        None,
         filesystem,
+        options,
     )
-    .map(|x| x.to_string())
+    .map(|(x, _)| x.to_string())
 }
 
 pub(crate) struct SingleFile {
@@ -70,7 +98,8 @@ pub fn render(source: String, context: &Value) -> Result<String, RenderError<()>
     render_with_custom_filesystem(&"input".to_owned(), &context, &SingleFile { data: source })
 }
 
-struct PathFilesystem {}
+/// The filesystem used by [`render_file`](fn@render_file), where templates reference each other by relative paths
+pub struct PathFilesystem {}
 
 #[derive(Debug)]
 pub enum PathFilesystemError {
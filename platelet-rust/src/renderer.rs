@@ -6,26 +6,147 @@ use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::rc::Rc;
 
-use crate::expression_eval::{eval, truthy, EvalError};
+use crate::expression_eval::{are_equal, eval, truthy, EvalError, SLOTS_KEY};
 use crate::expression_parser::expr;
-use crate::for_loop_parser::for_loop;
+use crate::for_loop_parser::{destructuring_pattern, for_loop, Pattern};
+use crate::for_loop_runner::bind;
 use crate::html::Node;
 use crate::html_parser::parse_html;
 use crate::text_node::render_text_node;
-use crate::types::Type;
+use crate::types::{type_of, Type};
 use crate::{for_loop_runner, text_node};
 
 /// Represents a filesystem you can traverse when you visit a `pl-src` tag.
 pub trait Filesystem<E> {
     /// Move from the current filename to another, given a path
     /// For example, the standard filesystem moves from `"/project/templates/index.html"` to
-    /// `"./component.html"` you arrive at `"/project/templates/component.html"`
+    /// `"./component.html"` you arrive at `"/project/templates/component.html"`.
+    /// It can fail if there's nothing at the new path, but doesn't have to: [`read`](Filesystem::read) failing works too.
     fn move_to(&self, current: &String, path: &String) -> Result<String, E>;
 
     /// Read a file at a given path
     fn read(&self, filename: &String) -> Result<String, E>;
 }
 
+/// What a `pl-use` or custom tag renders
+#[derive(Clone)]
+pub(crate) enum Component {
+    /// a template file, by its resolved path
+    File(String),
+    /// the content of a `<template pl-define>`, and the file it's in
+    Defined(Vec<Node>, String),
+}
+
+/// Content passed into a component, which is rendered in the caller's scope
+/// wherever the component places the matching `<slot>`
+#[derive(Clone)]
+pub(crate) struct SlotFill {
+    nodes: Vec<Node>,
+    vars: Value,
+    slots: Rc<Slots>,
+    components: Rc<Components>,
+    filename: String,
+    // from `pl-slot-props`, binds the `^` attributes of the `<slot>`
+    props: Option<Pattern>,
+}
+
+pub(crate) type Slots = HashMap<String, SlotFill>;
+
+/// The components a file declares with `<template pl-define>` or `<template pl-import>`, by name
+pub(crate) type Components = HashMap<String, Component>;
+
+/// A component being rendered, to detect runaway recursion
+#[derive(PartialEq)]
+pub(crate) struct Include {
+    name: String,
+    props: Value,
+}
+
+/// State shared across a whole render
+#[derive(Default)]
+pub(crate) struct RenderState {
+    /// `<style>` and `<script>` elements already output, which aren't repeated
+    already_included: HashSet<(String, String)>,
+    /// the components being rendered, outermost first
+    includes: Vec<Include>,
+    /// the template being rendered, which registered components are relative to
+    root: String,
+}
+
+impl RenderState {
+    pub(crate) fn new(filename: &String, vars: &Value) -> RenderState {
+        RenderState {
+            already_included: HashSet::new(),
+            includes: vec![Include {
+                name: filename.to_owned(),
+                props: vars.clone(),
+            }],
+            root: filename.to_owned(),
+        }
+    }
+}
+
+/// What rendering a template needs besides its variables
+pub(crate) struct RenderContext<'a, FS> {
+    /// content passed in by the caller, if this is a component
+    slots: Rc<Slots>,
+    /// the components the file can use by name
+    components: Rc<Components>,
+    state: &'a mut RenderState,
+    /// the file being rendered, which paths are relative to
+    filename: &'a String,
+    filesystem: &'a FS,
+    options: &'a RenderOptions,
+}
+
+impl<'a, FS> RenderContext<'a, FS> {
+    /// The context for rendering content from another file, or with other slots and components
+    fn enter<'b>(
+        &'b mut self,
+        slots: Rc<Slots>,
+        components: Rc<Components>,
+        filename: &'b String,
+    ) -> RenderContext<'b, FS> {
+        RenderContext {
+            slots,
+            components,
+            state: self.state,
+            filename,
+            filesystem: self.filesystem,
+            options: self.options,
+        }
+    }
+}
+
+/// The tag of an element wrapping content from another file, when templates are merged by `pl-extends`,
+/// so the content is rendered relative to the file it's written in. Parsed HTML can't have this tag,
+/// and it's output as just its children where rendering is skipped, like `pl-template`.
+pub(crate) const ORIGIN_TAG: &str = "#origin";
+
+/// Options that change how templates are rendered
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Fail with [`RenderErrorKind::UndefinedSlot`] when a slot isn't filled by the caller,
+    /// instead of rendering the slot's own content
+    pub strict_slots: bool,
+    /// Custom element names, like `ui-card`, mapped to the template they render.
+    /// Paths are relative to the template being rendered, and are only resolved when the tag is used.
+    pub components: HashMap<String, String>,
+    /// How deeply components can be nested inside each other, including a component
+    /// rendering itself, before failing with [`RenderErrorKind::MaxDepthExceeded`]
+    pub max_depth: usize,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            strict_slots: false,
+            components: HashMap::new(),
+            max_depth: 16,
+        }
+    }
+}
+
 enum PostRenderOperation {
     Nothing,
     ReplaceMeWith(Vec<Node>),
@@ -40,8 +161,20 @@ pub enum RenderErrorKind<FilesystemError> {
     ForLoopParser(String),
     ForLoopEval(for_loop_runner::Error),
     UndefinedSlot(String),
+    UndefinedComponent(String),
     BadPlIsName(String),
+    /// a name in `RenderOptions::components` that isn't a valid custom element name
+    BadComponentName(String),
+    SrcNotFound(String, FilesystemError),
     FilesystemError(FilesystemError),
+    /// a chain of `pl-extends` that leads back to a template already in it
+    ExtendsCycle(Vec<String>),
+    /// a component rendering itself with the same props as before, and the chain of components leading to it
+    IncludeCycle(Vec<String>),
+    /// components nested deeper than `RenderOptions::max_depth`, and the chain of components leading to it
+    MaxDepthExceeded(usize, Vec<String>),
+    /// a prop didn't match the component's `pl-prop` declaration: the calling file (if any), then the problem
+    InvalidProp(Option<String>, String),
 }
 
 impl<FilesystemError> fmt::Display for RenderErrorKind<FilesystemError>
@@ -75,8 +208,37 @@ where
                 }
             }
             RenderErrorKind::UndefinedSlot(e) => write!(f, "UNDEFINED SLOT: {:?}", e),
+            RenderErrorKind::UndefinedComponent(e) => {
+                write!(f, "UNDEFINED `pl-use` COMPONENT: {:?}", e)
+            }
             RenderErrorKind::BadPlIsName(e) => write!(f, "UNDEFINED `pl-is` NAME: {:?}", e),
+            RenderErrorKind::BadComponentName(e) => write!(
+                f,
+                "INVALID COMPONENT NAME: {:?}, names must contain a `-`, like custom elements",
+                e
+            ),
+            RenderErrorKind::SrcNotFound(src, e) => {
+                write!(f, "COULD NOT FIND `pl-src` {:?}: {:?}", src, e)
+            }
             RenderErrorKind::FilesystemError(e) => write!(f, "FILE SYSTEM ERROR: {:?}", e),
+            RenderErrorKind::ExtendsCycle(chain) => {
+                write!(f, "CYCLIC `pl-extends`: {}", chain.join(" -> "))
+            }
+            RenderErrorKind::IncludeCycle(chain) => {
+                write!(f, "CYCLIC INCLUDE: {}", chain.join(" -> "))
+            }
+            RenderErrorKind::MaxDepthExceeded(depth, chain) => {
+                write!(
+                    f,
+                    "MAXIMUM INCLUDE DEPTH ({}) EXCEEDED: {}",
+                    depth,
+                    chain.join(" -> ")
+                )
+            }
+            RenderErrorKind::InvalidProp(Some(caller), e) => {
+                write!(f, "INVALID PROP: {} (passed from {})", e, caller)
+            }
+            RenderErrorKind::InvalidProp(None, e) => write!(f, "INVALID PROP: {}", e),
         }
     }
 }
@@ -105,12 +267,9 @@ where
 fn render_elem<FS, FilesystemError>(
     node: &mut Node,
     vars: &Value,
-    slots: Rc<HashMap<String, Vec<Node>>>,
-    already_included: &mut HashSet<(String, String)>,
     previous_conditional: &Option<bool>,
     next_neighbour_conditional: &mut Option<bool>,
-    filename: &String,
-    filesystem: &FS,
+    ctx: &mut RenderContext<FS>,
 ) -> Result<PostRenderOperation, RenderError<FilesystemError>>
 where
     FS: Filesystem<FilesystemError>,
@@ -119,21 +278,14 @@ where
     match node {
         Node::Doctype { .. } => return Ok(PostRenderOperation::Nothing),
         Node::Document { children } => {
-            render_children(
-                children,
-                &[vars],
-                slots,
-                already_included,
-                filename,
-                filesystem,
-            )?;
+            render_children(children, &[vars], ctx)?;
             return Ok(PostRenderOperation::Nothing);
         }
         Node::Comment { .. } => return Ok(PostRenderOperation::Nothing),
         Node::Text { content: t, .. } => {
             let content = render_text_node(t.as_ref(), &vars).map_err(|e| RenderError {
                 kind: RenderErrorKind::TextRender(e),
-                filename: filename.to_owned(),
+                filename: ctx.filename.to_owned(),
             })?;
             let content = content.to_string();
             *t = content;
@@ -145,68 +297,50 @@ where
             name,
             ..
         } => {
-            if let Some(exp_index) = attrs_list.iter().position(|(name, _)| name == "pl-if") {
-                let (_, exp) = &attrs_list[exp_index];
-                let v = parse_eval(exp, vars).map_err(|e| RenderError {
-                    kind: e,
-                    filename: filename.to_owned(),
-                })?;
-                let cond = truthy(&v);
-                *next_neighbour_conditional = Some(cond);
-                if !cond {
-                    return Ok(PostRenderOperation::ReplaceMeWith(vec![]));
-                } else {
-                    attrs_list.remove(exp_index);
-                }
+            if name == ORIGIN_TAG {
+                let origin = attrs_list[0].1.clone();
+                let mut content = std::mem::take(children);
+                let slots = ctx.slots.clone();
+                let components = ctx.components.clone();
+                render_children(
+                    &mut content,
+                    &[vars],
+                    &mut ctx.enter(slots, components, &origin),
+                )?;
+                return Ok(PostRenderOperation::ReplaceMeWith(content));
             }
 
-            if let Some(exp_index) = attrs_list.iter().position(|(name, _)| name == "pl-else-if") {
-                let (_, exp) = &attrs_list[exp_index];
-                match previous_conditional {
-                    Some(true) => {
-                        *next_neighbour_conditional = Some(true);
-                        return Ok(PostRenderOperation::ReplaceMeWith(vec![]));
-                    }
-                    Some(false) => {
-                        let v = parse_eval(exp, vars).map_err(|e| RenderError {
-                            kind: e,
-                            filename: filename.clone(),
-                        })?;
-                        let cond = truthy(&v);
-                        *next_neighbour_conditional = Some(cond);
-                        if !cond {
-                            return Ok(PostRenderOperation::ReplaceMeWith(vec![]));
-                        } else {
-                            attrs_list.remove(exp_index);
-                        }
-                    }
-                    None => {
-                        return Err(RenderError {
-                            kind: RenderErrorKind::IllegalDirective(
-                                "encountered a pl-else-if that didn't follow an if".into(),
-                            ),
-                            filename: filename.to_owned(),
-                        })
-                    }
-                }
-            }
+            // first, so a `pl-default` on a misplaced `pl-prop` is reported as a `pl-prop` error
+            check_directives(name, attrs_list, vars, &ctx.components, ctx.options).map_err(
+                |e| RenderError {
+                    kind: e,
+                    filename: ctx.filename.to_owned(),
+                },
+            )?;
 
-            if let Some(index) = attrs_list.iter().position(|(name, _)| name == "pl-else") {
-                match previous_conditional {
-                    Some(true) => {
-                        return Ok(PostRenderOperation::ReplaceMeWith(vec![]));
-                    }
-                    Some(false) => {
-                        attrs_list.remove(index);
-                    }
-                    None => return Err(RenderError {
-                        kind: RenderErrorKind::IllegalDirective(
-                        "encountered a pl-else that didn't immediately for a pl-if or pl-else-if"
+            if attrs_list
+                .iter()
+                .any(|(name, _)| name == "pl-case" || name == "pl-default")
+            {
+                return Err(RenderError {
+                    kind: RenderErrorKind::IllegalDirective(
+                        "encountered a pl-case or pl-default that wasn't a child of a pl-switch"
                             .into(),
                     ),
-                    filename: filename.to_owned(),
-                }),
-                }
+                    filename: ctx.filename.to_owned(),
+                });
+            }
+
+            attrs_list.retain(|(name, _)| name != "pl-block");
+
+            if !conditional(
+                attrs_list,
+                vars,
+                previous_conditional,
+                next_neighbour_conditional,
+                ctx.filename,
+            )? {
+                return Ok(PostRenderOperation::ReplaceMeWith(vec![]));
             }
 
             if let Some(fl_index) = attrs_list.iter().position(|(name, _)| name == "pl-for") {
@@ -216,39 +350,42 @@ where
                     .map_err(RenderErrorKind::ForLoopParser)
                     .map_err(|e| RenderError {
                         kind: e,
-                        filename: filename.clone(),
+                        filename: ctx.filename.to_owned(),
                     })?;
                 let contexts = for_loop_runner::for_loop_runner(&fl, vars)
                     .map_err(RenderErrorKind::ForLoopEval)
                     .map_err(|e| RenderError {
                         kind: e,
-                        filename: filename.clone(),
+                        filename: ctx.filename.to_owned(),
                     })?;
                 attrs_list.remove(fl_index);
 
+                // a loop that renders nothing counts as a falsy branch,
+                // so a following `pl-else` can act as an "empty" fallback
+                *next_neighbour_conditional = Some(!contexts.is_empty());
+
                 let mut repeats = vec![];
 
                 for _ in &contexts {
                     repeats.push(node.clone());
                 }
 
-                render_children(
-                    &mut repeats,
-                    &contexts.iter().collect::<Vec<_>>(),
-                    slots.clone(),
-                    already_included,
-                    filename,
-                    filesystem,
-                )?;
+                render_children(&mut repeats, &contexts.iter().collect::<Vec<_>>(), ctx)?;
                 return Ok(PostRenderOperation::ReplaceMeWith(repeats));
             }
 
+            // the rest of the element is output as written
+            if let Some(index) = attrs_list.iter().position(|(name, _)| name == "pl-raw") {
+                attrs_list.remove(index);
+                return Ok(PostRenderOperation::Nothing);
+            }
+
             if let Some(exp_index) = attrs_list.iter().position(|(name, _)| name == "pl-is") {
                 let (_, exp) = &attrs_list[exp_index];
 
                 let v = parse_eval(&exp, vars).map_err(|e| RenderError {
                     kind: e,
-                    filename: filename.clone(),
+                    filename: ctx.filename.to_owned(),
                 })?;
                 match v {
                     Value::String(tag) => {
@@ -259,7 +396,7 @@ where
                         } else {
                             return Err(RenderError {
                                 kind: RenderErrorKind::BadPlIsName(tag),
-                                filename: filename.to_owned(),
+                                filename: ctx.filename.to_owned(),
                             });
                         };
                     }
@@ -268,18 +405,52 @@ where
                             kind: RenderErrorKind::IllegalDirective(
                                 "pl-is expects a string".into(),
                             ),
-                            filename: filename.to_owned(),
+                            filename: ctx.filename.to_owned(),
                         })
                     }
                 }
             }
 
+            if let Some(exp_index) = attrs_list.iter().position(|(name, _)| name == "pl-switch") {
+                let (_, exp) = &attrs_list[exp_index];
+
+                let subject = parse_eval(exp, vars).map_err(|e| RenderError {
+                    kind: e,
+                    filename: ctx.filename.to_owned(),
+                })?;
+                attrs_list.remove(exp_index);
+                select_case(children, &subject, vars, ctx.filename)?;
+            }
+
+            let mut render_contents = name != "script";
+
+            if let Some(exp_index) = attrs_list.iter().position(|(name, _)| name == "pl-text") {
+                let (_, exp) = &attrs_list[exp_index];
+
+                let v = parse_eval(exp, vars).map_err(|e| RenderError {
+                    kind: e,
+                    filename: ctx.filename.to_owned(),
+                })?;
+                attrs_list.remove(exp_index);
+
+                let separator = match attrs_list.iter().position(|(name, _)| name == "pl-join") {
+                    Some(join_index) => attrs_list.remove(join_index).1,
+                    None => ", ".to_owned(),
+                };
+
+                children.clear();
+                children.push(Node::Text {
+                    content: text_node::stringify_text(&v, &separator),
+                });
+                render_contents = false;
+            }
+
             if let Some(exp_index) = attrs_list.iter().position(|(name, _)| name == "pl-html") {
                 let (_, exp) = &attrs_list[exp_index];
 
                 let v = parse_eval(exp, vars).map_err(|e| RenderError {
                     kind: e,
-                    filename: filename.to_owned(),
+                    filename: ctx.filename.to_owned(),
                 })?;
                 match v {
                     Value::String(html) => {
@@ -287,107 +458,96 @@ where
                         attrs_list.remove(exp_index);
                         children.clear();
                         children.push(node);
-                        return Ok(PostRenderOperation::Nothing);
+                        render_contents = false;
                     }
                     _v => {
                         return Err(RenderError {
                             kind: RenderErrorKind::IllegalDirective(
                                 "pl-html expects a string".into(),
                             ),
-                            filename: filename.to_owned(),
+                            filename: ctx.filename.to_owned(),
                         })
                     }
                 }
             }
 
-            if let Some(src_index) = attrs_list.iter().position(|(name, _)| name == "pl-src") {
-                let (_, src) = &attrs_list[src_index];
-
-                let path = filesystem
-                    .move_to(filename, src)
-                    .map_err(RenderErrorKind::FilesystemError)
-                    .map_err(|e| RenderError {
-                        kind: e,
-                        filename: filename.to_owned(),
-                    })?;
-
-                let mut slots: HashMap<_, Vec<Node>> = HashMap::new();
-
-                let mut children = children.to_owned();
-
-                children.retain(|item| match item {
-                    Node::Element {
-                        name,
-                        attrs,
-                        children,
-                    } if name == "pl-template" => {
-                        let (_, slot_name) = attrs.iter().find(|(k, _)| k == "pl-slot").unwrap();
-                        slots.insert(slot_name.clone(), children.to_owned());
-                        false
-                    }
-                    _ => true,
-                });
-
-                slots.insert("".to_owned(), children);
-
-                let mut new_context = Map::new();
-
-                for (attr, val) in attrs_list.to_owned() {
-                    if let Some(attr) = attr.strip_prefix("^") {
-                        let v = parse_eval(&val, vars).map_err(|e| RenderError {
-                            kind: e,
-                            filename: filename.to_owned(),
-                        })?;
-                        new_context.insert(attr.to_string(), v);
-                    }
-                }
-
-                let rendered = render(
-                    &Value::Object(new_context),
-                    Rc::new(slots),
-                    already_included,
-                    &path,
-                    filesystem,
-                )?;
+            // `<ui-card>` is short for `<slot pl-use="ui-card">`
+            if name.contains('-')
+                && (ctx.components.contains_key(name.as_str())
+                    || ctx.options.components.contains_key(name.as_str()))
+                && !attrs_list
+                    .iter()
+                    .any(|(name, _)| name == "pl-src" || name == "^pl-src" || name == "pl-use")
+            {
+                attrs_list.push(("pl-use".to_owned(), name.clone()));
+            }
 
-                match rendered {
-                    Node::Document { children } => {
-                        return Ok(PostRenderOperation::ReplaceMeWith(vec![Node::Document {
-                            children: children.clone(),
-                        }]))
-                    }
-                    _ => panic!("I know that render only ever returns a document"),
-                }
+            if let Some(src_index) = attrs_list
+                .iter()
+                .position(|(name, _)| name == "pl-src" || name == "^pl-src" || name == "pl-use")
+            {
+                return render_component(src_index, attrs_list, children, vars, ctx);
             }
 
             if let Some(src_index) = attrs_list.iter().position(|(name, _)| name == "pl-slot") {
                 let (_, src) = &attrs_list[src_index];
 
+                let slots = ctx.slots.clone();
                 match slots.get(src) {
-                    Some(node) => return Ok(PostRenderOperation::ReplaceMeWith(node.clone())),
-                    None => {
+                    Some(fill) => {
+                        let mut fill_vars = fill.vars.clone();
+
+                        if let Some(pattern) = &fill.props {
+                            let mut props = Map::new();
+                            for (attr, val) in attrs_list.iter() {
+                                if let Some(attr) = attr.strip_prefix('^') {
+                                    let v = parse_eval(val, vars).map_err(|e| RenderError {
+                                        kind: e,
+                                        filename: ctx.filename.to_owned(),
+                                    })?;
+                                    props.insert(attr.to_owned(), v);
+                                }
+                            }
+                            let mut bindings = vec![];
+                            bind(pattern, Value::Object(props), &mut bindings)
+                                .map_err(RenderErrorKind::ForLoopEval)
+                                .map_err(|e| RenderError {
+                                    kind: e,
+                                    filename: fill.filename.to_owned(),
+                                })?;
+                            if let Value::Object(o) = &mut fill_vars {
+                                o.extend(bindings);
+                            }
+                        }
+
+                        let mut nodes = fill.nodes.clone();
+                        let mut fill_ctx =
+                            ctx.enter(fill.slots.clone(), fill.components.clone(), &fill.filename);
+                        render_children(&mut nodes, &[&fill_vars], &mut fill_ctx)?;
+                        return Ok(PostRenderOperation::ReplaceMeWith(nodes));
+                    }
+                    None if ctx.options.strict_slots => {
                         return Err(RenderError {
                             kind: RenderErrorKind::UndefinedSlot(src.clone()),
-                            filename: filename.to_owned(),
+                            filename: ctx.filename.to_owned(),
                         });
                     }
+                    None => {
+                        // the slot's own content is the default
+                        let mut default = children.to_owned();
+                        render_children(&mut default, &[vars], ctx)?;
+                        return Ok(PostRenderOperation::ReplaceMeWith(default));
+                    }
                 }
             }
 
             modify_attrs(attrs_list, vars).map_err(|e| RenderError {
                 kind: e,
-                filename: filename.clone(),
+                filename: ctx.filename.to_owned(),
             })?;
 
-            if name != "script" {
-                render_children(
-                    children,
-                    &[vars],
-                    slots,
-                    already_included,
-                    filename,
-                    filesystem,
-                )?;
+            if render_contents {
+                render_children(children, &[vars], ctx)?;
             } else {
                 // TODO - should I allow injecting script tags?
             }
@@ -403,10 +563,10 @@ where
                                 .collect::<Vec<_>>()
                                 .join("///"),
                     );
-                    if already_included.contains(&key) {
+                    if ctx.state.already_included.contains(&key) {
                         return Ok(PostRenderOperation::ReplaceMeWith(vec![]));
                     } else {
-                        already_included.insert(key);
+                        ctx.state.already_included.insert(key);
                     }
                 }
             }
@@ -416,119 +576,1487 @@ where
     }
 }
 
-fn render_children<FS, FilesystemError>(
+/// Anything other than whitespace and comments
+fn has_content(node: &Node) -> bool {
+    match node {
+        Node::Text { content } => !content.trim().is_empty(),
+        Node::Comment { .. } => false,
+        Node::Document { children } => children.iter().any(has_content),
+        _ => true,
+    }
+}
+
+fn is_slot_fill(node: &Node) -> bool {
+    match node {
+        Node::Element { name, attrs, .. } => {
+            name == "pl-template" && attrs.iter().any(|(k, _)| k == "pl-slot")
+        }
+        _ => false,
+    }
+}
+
+fn select_case<FilesystemError>(
     children: &mut Vec<Node>,
-    vars: &[&Value],
-    slots: Rc<HashMap<String, Vec<Node>>>,
-    already_included_styles: &mut HashSet<(String, String)>,
+    subject: &Value,
+    vars: &Value,
     filename: &String,
-    filesystem: &FS,
-) -> Result<(), RenderError<FilesystemError>>
-where
-    FS: Filesystem<FilesystemError>,
-    FilesystemError: fmt::Debug,
-{
-    let mut i = 0;
+) -> Result<(), RenderError<FilesystemError>> {
+    let mut matched = false;
+    let mut seen_default = false;
 
-    let mut get_this = None;
-    let mut set_this = None;
+    let mut i = 0;
     while i < children.len() {
-        let child = children[i].borrow_mut();
-        match render_elem(
-            child,
-            vars[i.min(vars.len() - 1)],
-            slots.clone(),
-            already_included_styles,
-            &get_this,
-            &mut set_this,
-            filename,
-            filesystem,
-        )? {
-            PostRenderOperation::Nothing => {
-                i += 1;
-            }
-            PostRenderOperation::ReplaceMeWith(nodes) => {
-                let _ = children.remove(i);
-                for node in nodes {
-                    children.insert(i, node);
-                    i += 1;
+        match &mut children[i] {
+            // the contents of a `<template pl-switch>`
+            Node::Document { children } => select_case(children, subject, vars, filename)?,
+            Node::Element { attrs, .. } => {
+                // the case is chosen before the element is rendered, so its condition would be ignored
+                if attrs
+                    .iter()
+                    .any(|(name, _)| name == "pl-case" || name == "pl-default")
+                {
+                    if let Some((directive, _)) = attrs.iter().find(|(name, _)| {
+                        name == "pl-if" || name == "pl-else-if" || name == "pl-else"
+                    }) {
+                        return Err(RenderError {
+                            kind: RenderErrorKind::IllegalDirective(format!(
+                                "{} can't be used on a pl-case or pl-default, \
+                                 add the condition to the pl-case instead",
+                                directive
+                            )),
+                            filename: filename.to_owned(),
+                        });
+                    }
+                    if attrs.iter().any(|(name, _)| name == "pl-case")
+                        && attrs.iter().any(|(name, _)| name == "pl-default")
+                    {
+                        return Err(RenderError {
+                            kind: RenderErrorKind::IllegalDirective(
+                                "pl-case and pl-default can't be used on the same element".into(),
+                            ),
+                            filename: filename.to_owned(),
+                        });
+                    }
+                    check_loop_variables(attrs, vars).map_err(|e| RenderError {
+                        kind: e,
+                        filename: filename.to_owned(),
+                    })?;
+                }
+                if let Some(case_index) = attrs.iter().position(|(name, _)| name == "pl-case") {
+                    if seen_default {
+                        return Err(RenderError {
+                            kind: RenderErrorKind::IllegalDirective(
+                                "encountered a pl-case after a pl-default".into(),
+                            ),
+                            filename: filename.to_owned(),
+                        });
+                    }
+                    let is_match = !matched && {
+                        let v =
+                            parse_eval(&attrs[case_index].1, vars).map_err(|e| RenderError {
+                                kind: e,
+                                filename: filename.to_owned(),
+                            })?;
+                        are_equal(&v, subject)
+                    };
+                    if is_match {
+                        matched = true;
+                        attrs.remove(case_index);
+                    } else {
+                        children.remove(i);
+                        continue;
+                    }
+                } else if let Some(default_index) =
+                    attrs.iter().position(|(name, _)| name == "pl-default")
+                {
+                    if seen_default {
+                        return Err(RenderError {
+                            kind: RenderErrorKind::IllegalDirective(
+                                "encountered more than one pl-default in a pl-switch".into(),
+                            ),
+                            filename: filename.to_owned(),
+                        });
+                    }
+                    seen_default = true;
+                    if matched {
+                        children.remove(i);
+                        continue;
+                    } else {
+                        matched = true;
+                        attrs.remove(default_index);
+                    }
                 }
             }
-        };
-        get_this = set_this;
+            _ => {}
+        }
+        i += 1;
     }
 
     Ok(())
 }
 
-fn attrify(val: &Value) -> Option<String> {
-    match val {
-        Value::Null => None,
-        Value::Bool(false) => None,
-        Value::Bool(true) => Some("true".into()),
-        Value::Number(n) => Some(n.to_string()),
-        Value::String(s) => Some(s.to_string()),
-        Value::Array(a) => {
-            let xs: Vec<_> = a.iter().filter_map(attrify).collect();
+const DIRECTIVES: [&str; 27] = [
+    "pl-if",
+    "pl-else-if",
+    "pl-else",
+    "pl-switch",
+    "pl-case",
+    "pl-default",
+    "pl-for",
+    "pl-text",
+    "pl-join",
+    "pl-html",
+    "pl-attrs",
+    "pl-src",
+    "^pl-src",
+    "pl-optional",
+    "pl-prop",
+    "pl-type",
+    "pl-fallthrough",
+    "pl-slot",
+    "pl-slot-props",
+    "pl-define",
+    "pl-use",
+    "pl-import",
+    "pl-extends",
+    "pl-block",
+    "pl-super",
+    "pl-is",
+    "pl-raw",
+];
 
-            if xs.is_empty() {
-                None
-            } else {
-                Some(xs.join(" "))
-            }
-        }
-        Value::Object(o) => {
-            let xs: Vec<_> = o
-                .iter()
-                .filter_map(|(k, v)| if truthy(v) { Some(k.to_owned()) } else { None })
-                .collect();
+/// Rejects `pl-` attributes that aren't directives, and directives that would have no effect
+fn check_directives<FilesystemError>(
+    name: &str,
+    attrs: &[(String, String)],
+    vars: &Value,
+    components: &Components,
+    options: &RenderOptions,
+) -> Result<(), RenderErrorKind<FilesystemError>> {
+    let has = |directive: &str| attrs.iter().any(|(k, _)| k == directive);
+    let illegal = |e: String| Err(RenderErrorKind::IllegalDirective(e));
 
-            if xs.is_empty() {
-                None
-            } else {
-                Some(xs.join(" "))
-            }
+    for (attr, _) in attrs {
+        if !(attr.starts_with("pl-") || attr.starts_with("^pl-"))
+            || DIRECTIVES.contains(&attr.as_str())
+        {
+            continue;
         }
+        let suggestion = DIRECTIVES
+            .iter()
+            .map(|directive| (edit_distance(attr, directive), directive))
+            .min()
+            .filter(|(distance, _)| *distance <= 2);
+        return match suggestion {
+            Some((_, directive)) => illegal(format!(
+                "unknown directive {:?}, did you mean {:?}?",
+                attr, directive
+            )),
+            None => illegal(format!("unknown directive {:?}", attr)),
+        };
     }
-}
 
-fn modify_attrs<FileSystemError>(
-    attrs: &mut Vec<(String, String)>,
-    vars: &Value,
-) -> Result<(), RenderErrorKind<FileSystemError>> {
-    let mut ret: Result<(), RenderErrorKind<FileSystemError>> = Ok(());
-
-    attrs.retain_mut(|(name_original, val)| {
-        if let Some(name) = name_original.strip_prefix('^') {
-            match parse_eval(&val, vars) {
-                Ok(v) => match attrify(&v) {
-                    None => false,
-                    Some(s) => {
-                        *name_original = name.to_owned();
-                        *val = s;
-                        true
-                    }
-                },
-                Err(e) => {
-                    ret = Err(e);
-                    false
-                }
-            }
-        } else {
-            true
-        }
-    });
+    let is_component = has("pl-src")
+        || has("^pl-src")
+        || has("pl-use")
+        || (name.contains('-')
+            && (components.contains_key(name) || options.components.contains_key(name)));
+    // at most one of these, named in errors as it's written
+    let sources: Vec<_> = ["pl-src", "^pl-src", "pl-use"]
+        .into_iter()
+        .filter(|directive| has(directive))
+        .collect();
+    if let [a, b, ..] = sources[..] {
+        return illegal(format!("{} and {} can't be used on the same element", a, b));
+    }
+    let component = sources.first().copied().unwrap_or("pl-src");
 
-    ret
+    for directive in ["pl-prop", "pl-type", "pl-define", "pl-import", "pl-extends"] {
+        if has(directive) {
+            return illegal(format!(
+                "{} only works on a <template> at the top level of a template",
+                directive
+            ));
+        }
+    }
+    if has("pl-slot") && name != "slot" && name != "pl-template" {
+        return illegal("pl-slot only works on a <slot> or a <template>".into());
+    }
+    if has("pl-slot-props") {
+        return illegal(
+            "pl-slot-props only works on a <template pl-slot> filling a component's slot".into(),
+        );
+    }
+    if has("pl-join") && !has("pl-text") {
+        return illegal("pl-join only works alongside pl-text".into());
+    }
+    if has("pl-optional") && !(has("pl-src") || has("^pl-src")) {
+        return illegal("pl-optional only works alongside pl-src".into());
+    }
+    if has("pl-fallthrough") && !is_component {
+        return illegal("pl-fallthrough only works on a component".into());
+    }
+    if has("pl-super") {
+        return illegal("pl-super only works inside a pl-block override".into());
+    }
+
+    if has("pl-raw") {
+        let conditionals_and_loops = ["pl-if", "pl-else-if", "pl-else", "pl-for", "pl-raw"];
+        if let Some((directive, _)) = attrs.iter().find(|(k, _)| {
+            (k.starts_with("pl-") || k.starts_with("^pl-"))
+                && !conditionals_and_loops.contains(&k.as_str())
+        }) {
+            return illegal(format!("{} has no effect alongside pl-raw", directive));
+        }
+    }
+
+    let conditionals = ["pl-if", "pl-else-if", "pl-else"];
+    if conditionals.iter().filter(|d| has(d)).count() > 1 {
+        return illegal("an element can only have one of pl-if, pl-else-if and pl-else".into());
+    }
+
+    // each of these decides the element's content
+    let content: Vec<_> = [
+        ("pl-switch", has("pl-switch")),
+        ("pl-text", has("pl-text")),
+        ("pl-html", has("pl-html")),
+        (component, is_component),
+        ("pl-slot", has("pl-slot")),
+    ]
+    .into_iter()
+    .filter_map(|(directive, used)| if used { Some(directive) } else { None })
+    .collect();
+    if let [a, b, ..] = content[..] {
+        return illegal(format!("{} and {} can't be used on the same element", a, b));
+    }
+
+    // these replace the element, so its tag and attributes aren't used
+    if let Some(replaced) = content
+        .iter()
+        .find(|directive| **directive == component || **directive == "pl-slot")
+    {
+        for directive in ["pl-is", "pl-attrs"] {
+            if has(directive) {
+                return illegal(format!(
+                    "{} has no effect alongside {}",
+                    directive, replaced
+                ));
+            }
+        }
+    }
+
+    check_loop_variables(attrs, vars)
+}
+
+/// `pl-if`, `pl-else-if` and `pl-case` are checked once, before `pl-for` repeats the element,
+/// so can't use the loop's variables. `loop` is fine when it's from an enclosing loop.
+fn check_loop_variables<FilesystemError>(
+    attrs: &[(String, String)],
+    vars: &Value,
+) -> Result<(), RenderErrorKind<FilesystemError>> {
+    if let Some((_, fl)) = attrs.iter().find(|(k, _)| k == "pl-for") {
+        if let Ok(fl) = for_loop(&mut fl.as_str()) {
+            let mut bound = fl.bound_names();
+            if vars.get("loop").is_some() {
+                bound.retain(|n| *n != "loop");
+            }
+            for (directive, exp) in attrs
+                .iter()
+                .filter(|(k, _)| k == "pl-if" || k == "pl-else-if" || k == "pl-case")
+            {
+                let Ok(exp) = expr(&mut exp.as_str()) else {
+                    continue;
+                };
+                if let Some(name) = bound.iter().find(|n| exp.references(n)) {
+                    let hint = match directive.as_str() {
+                        "pl-case" => "try a pl-switch inside the loop instead",
+                        _ => "try an `if` guard instead: pl-for=\"... if ...\"",
+                    };
+                    return Err(RenderErrorKind::IllegalDirective(format!(
+                        "{} is checked before pl-for, so it can't use the loop variable {:?}, {}",
+                        directive, name, hint
+                    )));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The number of single character insertions, deletions, substitutions and swaps of
+/// neighbouring characters to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for i in 0..=a.len() {
+        for j in 0..=b.len() {
+            d[i][j] = if i == 0 || j == 0 {
+                i + j
+            } else {
+                let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+                let mut distance = (d[i - 1][j] + 1)
+                    .min(d[i][j - 1] + 1)
+                    .min(d[i - 1][j - 1] + cost);
+                if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                    distance = distance.min(d[i - 2][j - 2] + 1);
+                }
+                distance
+            };
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+/// Evaluates `pl-if`, `pl-else-if` and `pl-else`, removing them from `attrs_list`.
+/// Returns `false` if the element should be dropped.
+fn conditional<FilesystemError>(
+    attrs_list: &mut Vec<(String, String)>,
+    vars: &Value,
+    previous_conditional: &Option<bool>,
+    next_neighbour_conditional: &mut Option<bool>,
+    filename: &String,
+) -> Result<bool, RenderError<FilesystemError>> {
+    if let Some(exp_index) = attrs_list.iter().position(|(name, _)| name == "pl-if") {
+        let (_, exp) = &attrs_list[exp_index];
+        let v = parse_eval(exp, vars).map_err(|e| RenderError {
+            kind: e,
+            filename: filename.to_owned(),
+        })?;
+        let cond = truthy(&v);
+        *next_neighbour_conditional = Some(cond);
+        if !cond {
+            return Ok(false);
+        } else {
+            attrs_list.remove(exp_index);
+        }
+    }
+
+    if let Some(exp_index) = attrs_list.iter().position(|(name, _)| name == "pl-else-if") {
+        let (_, exp) = &attrs_list[exp_index];
+        match previous_conditional {
+            Some(true) => {
+                *next_neighbour_conditional = Some(true);
+                return Ok(false);
+            }
+            Some(false) => {
+                let v = parse_eval(exp, vars).map_err(|e| RenderError {
+                    kind: e,
+                    filename: filename.clone(),
+                })?;
+                let cond = truthy(&v);
+                *next_neighbour_conditional = Some(cond);
+                if !cond {
+                    return Ok(false);
+                } else {
+                    attrs_list.remove(exp_index);
+                }
+            }
+            None => {
+                return Err(RenderError {
+                    kind: RenderErrorKind::IllegalDirective(
+                        "encountered a pl-else-if that didn't follow an if".into(),
+                    ),
+                    filename: filename.to_owned(),
+                })
+            }
+        }
+    }
+
+    if let Some(index) = attrs_list.iter().position(|(name, _)| name == "pl-else") {
+        match previous_conditional {
+            Some(true) => {
+                return Ok(false);
+            }
+            Some(false) => {
+                attrs_list.remove(index);
+            }
+            None => {
+                return Err(RenderError {
+                    kind: RenderErrorKind::IllegalDirective(
+                        "encountered a pl-else that didn't immediately for a pl-if or pl-else-if"
+                            .into(),
+                    ),
+                    filename: filename.to_owned(),
+                })
+            }
+        }
+    }
+
+    Ok(true)
+}
+
+fn render_children<FS, FilesystemError>(
+    children: &mut Vec<Node>,
+    vars: &[&Value],
+    ctx: &mut RenderContext<FS>,
+) -> Result<(), RenderError<FilesystemError>>
+where
+    FS: Filesystem<FilesystemError>,
+    FilesystemError: fmt::Debug,
+{
+    let mut i = 0;
+
+    let mut get_this = None;
+    let mut set_this = None;
+    while i < children.len() {
+        let child = children[i].borrow_mut();
+        match render_elem(
+            child,
+            vars[i.min(vars.len() - 1)],
+            &get_this,
+            &mut set_this,
+            ctx,
+        )? {
+            PostRenderOperation::Nothing => {
+                i += 1;
+            }
+            PostRenderOperation::ReplaceMeWith(nodes) => {
+                let _ = children.remove(i);
+                for node in nodes {
+                    children.insert(i, node);
+                    i += 1;
+                }
+            }
+        };
+        get_this = set_this;
+    }
+
+    Ok(())
+}
+
+fn attrify(val: &Value) -> Option<String> {
+    match val {
+        Value::Null => None,
+        Value::Bool(false) => None,
+        Value::Bool(true) => Some("true".into()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::String(s) => Some(s.to_string()),
+        Value::Array(a) => {
+            let xs: Vec<_> = a.iter().filter_map(attrify).collect();
+
+            if xs.is_empty() {
+                None
+            } else {
+                Some(xs.join(" "))
+            }
+        }
+        Value::Object(o) => {
+            let xs: Vec<_> = o
+                .iter()
+                .filter_map(|(k, v)| if truthy(v) { Some(k.to_owned()) } else { None })
+                .collect();
+
+            if xs.is_empty() {
+                None
+            } else {
+                Some(xs.join(" "))
+            }
+        }
+    }
+}
+
+fn is_valid_attr_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with("pl-")
+        && !name.starts_with('^')
+        && !name.chars().any(|c| {
+            c.is_whitespace() || c.is_control() || matches!(c, '"' | '\'' | '>' | '/' | '=')
+        })
+}
+
+fn styleify(val: &Value) -> Option<String> {
+    match val {
+        Value::Array(a) => {
+            let xs: Vec<_> = a.iter().filter_map(styleify).collect();
+
+            if xs.is_empty() {
+                None
+            } else {
+                Some(xs.join("; "))
+            }
+        }
+        Value::Object(o) => {
+            let xs: Vec<_> = o
+                .iter()
+                .filter_map(|(k, v)| attrify(v).map(|v| format!("{}: {}", k, v)))
+                .collect();
+
+            if xs.is_empty() {
+                None
+            } else {
+                Some(xs.join("; "))
+            }
+        }
+        _ => attrify(val),
+    }
+}
+
+/// Attributes that are either present or absent, like `<input disabled>`
+pub(crate) fn is_boolean_attribute(name: &str) -> bool {
+    matches!(
+        name,
+        "allowfullscreen"
+            | "async"
+            | "autofocus"
+            | "autoplay"
+            | "checked"
+            | "controls"
+            | "default"
+            | "defer"
+            | "disabled"
+            | "formnovalidate"
+            | "hidden"
+            | "inert"
+            | "ismap"
+            | "itemscope"
+            | "loop"
+            | "multiple"
+            | "muted"
+            | "nomodule"
+            | "novalidate"
+            | "open"
+            | "playsinline"
+            | "readonly"
+            | "required"
+            | "reversed"
+            | "selected"
+    )
+}
+
+/// Attributes that take the strings `"true"` and `"false"`, like `aria-expanded`
+fn is_true_false_attribute(name: &str) -> bool {
+    name.starts_with("aria-") || matches!(name, "contenteditable" | "draggable" | "spellcheck")
+}
+
+fn attr_value(name: &str, val: &Value) -> Option<String> {
+    match name {
+        "style" => styleify(val),
+        _ if is_boolean_attribute(name) => match val {
+            Value::Bool(true) => Some("".into()),
+            _ => attrify(val),
+        },
+        _ if is_true_false_attribute(name) => match val {
+            Value::Bool(b) => Some(b.to_string()),
+            _ => attrify(val),
+        },
+        _ => attrify(val),
+    }
+}
+
+/// Where an attribute came from, in increasing order of precedence
+#[derive(Clone, PartialEq, PartialOrd)]
+enum AttrSource {
+    Spread,
+    Static,
+    Dynamic,
+}
+
+fn merge_attr_values(name: &str, a: Option<String>, b: Option<String>) -> Option<String> {
+    match (a, b) {
+        (Some(a), Some(b)) if name == "class" => {
+            let mut classes: Vec<&str> = vec![];
+            for class in a.split_whitespace().chain(b.split_whitespace()) {
+                if !classes.contains(&class) {
+                    classes.push(class);
+                }
+            }
+            Some(classes.join(" "))
+        }
+        (Some(a), Some(b)) if name == "style" => {
+            let declarations: Vec<_> = [&a, &b]
+                .iter()
+                .map(|s| s.trim().trim_end_matches(';').trim_end())
+                .filter(|s| !s.is_empty())
+                .collect();
+            Some(declarations.join("; "))
+        }
+        (a, b) => a.or(b),
+    }
+}
+
+/// `userId` => `data-user-id`, the reverse of `HTMLElement.dataset`
+fn data_attr_name(key: &str) -> String {
+    let mut name = "data-".to_owned();
+    for (i, c) in key.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                name.push('-');
+            }
+            name.push(c.to_ascii_lowercase());
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
+
+fn dataify(val: &Value) -> Option<String> {
+    match val {
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Array(_) | Value::Object(_) => Some(val.to_string()),
+        _ => attrify(val),
+    }
+}
+
+fn push_evaluated_attr<FileSystemError>(
+    evaluated: &mut Vec<(String, Option<String>, AttrSource)>,
+    name: String,
+    val: &Value,
+    source: AttrSource,
+) -> Result<(), RenderErrorKind<FileSystemError>> {
+    match val {
+        // `^data="{ id: 3 }"` expands to `data-id="3"`
+        Value::Object(o) if name == "data" => {
+            for (key, v) in o {
+                let name = data_attr_name(key);
+                if !is_valid_attr_name(&name) {
+                    return Err(RenderErrorKind::IllegalDirective(format!(
+                        "^data can't set an attribute called {:?}",
+                        name
+                    )));
+                }
+                evaluated.push((name, dataify(v), source.clone()));
+            }
+        }
+        _ => {
+            let value = attr_value(&name, val);
+            evaluated.push((name, value, source));
+        }
+    }
+    Ok(())
+}
+
+/// Renders the component an element refers to with `pl-src`, `^pl-src` or `pl-use`,
+/// the element's attributes being its props and its children filling its slots
+fn render_component<FS, FilesystemError>(
+    src_index: usize,
+    attrs_list: &[(String, String)],
+    children: &[Node],
+    vars: &Value,
+    ctx: &mut RenderContext<FS>,
+) -> Result<PostRenderOperation, RenderError<FilesystemError>>
+where
+    FS: Filesystem<FilesystemError>,
+    FilesystemError: fmt::Debug,
+{
+    let (attr, src) = &attrs_list[src_index];
+
+    let component = if attr == "pl-use" {
+        match (ctx.components.get(src), ctx.options.components.get(src)) {
+            (Some(component), _) => component.clone(),
+            // registered components are relative to the template being rendered
+            (None, Some(path)) => {
+                Component::File(ctx.filesystem.move_to(&ctx.state.root, path).map_err(|e| {
+                    RenderError {
+                        kind: RenderErrorKind::SrcNotFound(path.clone(), e),
+                        filename: ctx.filename.to_owned(),
+                    }
+                })?)
+            }
+            (None, None) => {
+                return Err(RenderError {
+                    kind: RenderErrorKind::UndefinedComponent(src.clone()),
+                    filename: ctx.filename.to_owned(),
+                })
+            }
+        }
+    } else {
+        let src = if attr == "^pl-src" {
+            match parse_eval(src, vars).map_err(|e| RenderError {
+                kind: e,
+                filename: ctx.filename.to_owned(),
+            })? {
+                Value::String(src) => src,
+                _ => {
+                    return Err(RenderError {
+                        kind: RenderErrorKind::IllegalDirective("^pl-src expects a string".into()),
+                        filename: ctx.filename.to_owned(),
+                    })
+                }
+            }
+        } else {
+            src.to_owned()
+        };
+
+        let optional = attrs_list.iter().any(|(name, _)| name == "pl-optional");
+
+        // `move_to` doesn't have to check that the file exists, so an optional one is read first
+        let path =
+            match ctx
+                .filesystem
+                .move_to(ctx.filename, &src)
+                .and_then(|path| match optional {
+                    true => ctx.filesystem.read(&path).map(|_| path),
+                    false => Ok(path),
+                }) {
+                Ok(path) => path,
+                Err(_) if optional => {
+                    // render the element's own content in place of the missing template
+                    let mut fallback = children.to_owned();
+                    fallback.retain(|child| !is_slot_fill(child));
+                    render_children(&mut fallback, &[vars], ctx)?;
+                    return Ok(PostRenderOperation::ReplaceMeWith(fallback));
+                }
+                Err(e) => {
+                    return Err(RenderError {
+                        kind: RenderErrorKind::SrcNotFound(src.clone(), e),
+                        filename: ctx.filename.to_owned(),
+                    })
+                }
+            };
+
+        Component::File(path)
+    };
+
+    let mut fills = Slots::new();
+    let mut default_slot = vec![];
+
+    let mut previous_fill_conditional = None;
+
+    for child in children.iter() {
+        if !is_slot_fill(child) {
+            if let Node::Element { .. } = child {
+                previous_fill_conditional = None;
+            }
+            default_slot.push(child.clone());
+            continue;
+        }
+
+        let mut fill = child.clone();
+        let Node::Element {
+            attrs, children, ..
+        } = &mut fill
+        else {
+            unreachable!()
+        };
+
+        let mut next_fill_conditional = previous_fill_conditional;
+        let keep = conditional(
+            attrs,
+            vars,
+            &previous_fill_conditional,
+            &mut next_fill_conditional,
+            ctx.filename,
+        )?;
+        previous_fill_conditional = next_fill_conditional;
+        if !keep {
+            continue;
+        }
+
+        let mut slot_name = String::new();
+        let mut props = None;
+        let mut loop_attrs = vec![];
+        for (k, v) in attrs.drain(..) {
+            match k.as_str() {
+                "pl-slot" => slot_name = v,
+                "pl-slot-props" => {
+                    props = Some(
+                        destructuring_pattern(&mut v.as_str())
+                            .map_err(RenderErrorKind::ForLoopParser)
+                            .map_err(|e| RenderError {
+                                kind: e,
+                                filename: ctx.filename.to_owned(),
+                            })?,
+                    )
+                }
+                "pl-for" => loop_attrs.push((k, v)),
+                _ if k.starts_with("pl-") || k.starts_with('^') => {
+                    return Err(RenderError {
+                        kind: RenderErrorKind::IllegalDirective(format!(
+                            "a slot fill can't have the attribute {:?}",
+                            k
+                        )),
+                        filename: ctx.filename.to_owned(),
+                    })
+                }
+                _ => {}
+            }
+        }
+
+        // a looped fill keeps its `pl-for`, to be expanded when the slot is rendered
+        let nodes = if loop_attrs.is_empty() {
+            children.to_owned()
+        } else {
+            vec![Node::Element {
+                name: "pl-template".to_owned(),
+                attrs: loop_attrs,
+                children: children.to_owned(),
+            }]
+        };
+
+        if fills.contains_key(&slot_name) {
+            return Err(RenderError {
+                kind: RenderErrorKind::IllegalDirective(format!(
+                    "the slot {:?} was filled more than once",
+                    slot_name
+                )),
+                filename: ctx.filename.to_owned(),
+            });
+        }
+
+        fills.insert(
+            slot_name,
+            SlotFill {
+                nodes,
+                vars: vars.clone(),
+                slots: ctx.slots.clone(),
+                components: ctx.components.clone(),
+                filename: ctx.filename.to_owned(),
+                props,
+            },
+        );
+    }
+
+    if default_slot.iter().any(has_content) {
+        if fills.contains_key("") {
+            return Err(RenderError {
+                kind: RenderErrorKind::IllegalDirective(
+                    "the default slot was filled by both a pl-slot template and other content"
+                        .into(),
+                ),
+                filename: ctx.filename.to_owned(),
+            });
+        }
+        fills.insert(
+            "".to_owned(),
+            SlotFill {
+                nodes: default_slot,
+                vars: vars.clone(),
+                slots: ctx.slots.clone(),
+                components: ctx.components.clone(),
+                filename: ctx.filename.to_owned(),
+                props: None,
+            },
+        );
+    }
+
+    let mut new_context = Map::new();
+
+    for (attr, val) in attrs_list.iter() {
+        if attr == "^pl-src" {
+            continue;
+        }
+        if let Some(attr) = attr.strip_prefix("^") {
+            let v = parse_eval(val, vars).map_err(|e| RenderError {
+                kind: e,
+                filename: ctx.filename.to_owned(),
+            })?;
+            new_context.insert(attr.to_string(), v);
+        } else if !attr.starts_with("pl-") {
+            // a `^` attribute of the same name takes precedence
+            new_context
+                .entry(attr.clone())
+                .or_insert(Value::String(val.clone()));
+        }
+    }
+
+    let filled: Map<_, _> = fills.keys().map(|k| (k.clone(), true.into())).collect();
+    new_context.insert(SLOTS_KEY.to_owned(), filled.into());
+
+    let include = Include {
+        name: match &component {
+            Component::File(path) => path.to_owned(),
+            Component::Defined(_, defined_in) => format!("{}#{}", defined_in, src),
+        },
+        props: Value::Object(new_context.clone()),
+    };
+    let chain = || {
+        ctx.state
+            .includes
+            .iter()
+            .map(|include| include.name.clone())
+            .chain([include.name.clone()])
+            .collect()
+    };
+    if ctx.state.includes.contains(&include) {
+        return Err(RenderError {
+            kind: RenderErrorKind::IncludeCycle(chain()),
+            filename: ctx.filename.to_owned(),
+        });
+    }
+    if ctx.state.includes.len() > ctx.options.max_depth {
+        return Err(RenderError {
+            kind: RenderErrorKind::MaxDepthExceeded(ctx.options.max_depth, chain()),
+            filename: ctx.filename.to_owned(),
+        });
+    }
+    ctx.state.includes.push(include);
+
+    let (rendered, declared, component_filename) = match component {
+        Component::File(path) => {
+            let (rendered, declared) = render(
+                &Value::Object(new_context),
+                Rc::new(fills),
+                ctx.state,
+                &path,
+                Some(ctx.filename),
+                ctx.filesystem,
+                ctx.options,
+            )?;
+            (rendered, declared, path)
+        }
+        Component::Defined(nodes, defined_in) => {
+            let caller = ctx.filename;
+            let components = ctx.components.clone();
+            let (rendered, declared) = render_defined(
+                nodes,
+                &Value::Object(new_context),
+                caller,
+                &mut ctx.enter(Rc::new(fills), components, &defined_in),
+            )?;
+            (rendered, declared, defined_in)
+        }
+    };
+
+    ctx.state.includes.pop();
+
+    match rendered {
+        Node::Document { mut children } => {
+            if attrs_list.iter().any(|(name, _)| name == "pl-fallthrough") {
+                let mut fallthrough = vec![];
+                for (attr, val) in attrs_list.iter() {
+                    if attr.starts_with("pl-") || attr == "^pl-src" {
+                        continue;
+                    }
+                    match attr.strip_prefix('^') {
+                        Some(name) if !declared.iter().any(|d| d == name) => {
+                            let v = parse_eval(val, vars).map_err(|e| RenderError {
+                                kind: e,
+                                filename: ctx.filename.to_owned(),
+                            })?;
+                            push_evaluated_attr(
+                                &mut fallthrough,
+                                name.to_owned(),
+                                &v,
+                                AttrSource::Dynamic,
+                            )
+                            .map_err(|e| RenderError {
+                                kind: e,
+                                filename: ctx.filename.to_owned(),
+                            })?;
+                        }
+                        None if !declared.contains(attr) => {
+                            fallthrough.push((attr.clone(), Some(val.clone()), AttrSource::Static))
+                        }
+                        _ => {}
+                    }
+                }
+                fall_through(&mut children, fallthrough).map_err(|e| RenderError {
+                    kind: e,
+                    filename: component_filename,
+                })?;
+            }
+
+            Ok(PostRenderOperation::ReplaceMeWith(vec![Node::Document {
+                children,
+            }]))
+        }
+        _ => panic!("I know that render only ever returns a document"),
+    }
+}
+
+/// The elements and text that make up rendered content, looking inside templates and
+/// nested components, which don't output an element of their own
+fn root_nodes(nodes: &mut [Node]) -> Vec<&mut Node> {
+    let mut roots = vec![];
+    for node in nodes.iter_mut() {
+        let is_root = match node {
+            Node::Element { name, .. } => {
+                name != "pl-template" && name != "style" && name != "script"
+            }
+            Node::Text { content } => !content.trim().is_empty(),
+            _ => false,
+        };
+        match node {
+            _ if is_root => roots.push(node),
+            Node::Document { children } => roots.extend(root_nodes(children)),
+            Node::Element { name, children, .. } if name == "pl-template" => {
+                roots.extend(root_nodes(children))
+            }
+            _ => {}
+        }
+    }
+    roots
+}
+
+/// Merges the attributes a caller put on a `pl-fallthrough` component onto the
+/// component's root element. `class` and `style` are merged, otherwise the caller wins.
+fn fall_through<FileSystemError>(
+    children: &mut [Node],
+    attrs: Vec<(String, Option<String>, AttrSource)>,
+) -> Result<(), RenderErrorKind<FileSystemError>> {
+    let mut roots = root_nodes(children);
+
+    let root_attrs = match &mut roots[..] {
+        [Node::Element { attrs, .. }] => attrs,
+        _ => {
+            return Err(RenderErrorKind::IllegalDirective(format!(
+                "pl-fallthrough needs a component with exactly one root element, found {}",
+                roots.len()
+            )))
+        }
+    };
+
+    for (name, value, _) in attrs {
+        match root_attrs.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, existing)) => {
+                let merged = if name == "class" || name == "style" {
+                    merge_attr_values(&name, Some(existing.clone()), value)
+                } else {
+                    value
+                };
+                match merged {
+                    Some(merged) => *existing = merged,
+                    None => root_attrs.retain(|(n, _)| *n != name),
+                }
+            }
+            None => {
+                if let Some(value) = value {
+                    root_attrs.push((name, value));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn modify_attrs<FileSystemError>(
+    attrs: &mut Vec<(String, String)>,
+    vars: &Value,
+) -> Result<(), RenderErrorKind<FileSystemError>> {
+    let mut evaluated: Vec<(String, Option<String>, AttrSource)> = vec![];
+    // boolean attributes that a `^` attribute sets to `false`
+    let mut switched_off: Vec<String> = vec![];
+
+    for (name, val) in attrs.drain(..) {
+        if name == "pl-attrs" {
+            let spread = match parse_eval(&val, vars)? {
+                Value::Object(o) => o,
+                Value::Null => Map::new(),
+                _ => {
+                    return Err(RenderErrorKind::IllegalDirective(
+                        "pl-attrs expects an object".into(),
+                    ))
+                }
+            };
+            for (name, v) in spread {
+                if !is_valid_attr_name(&name) {
+                    return Err(RenderErrorKind::IllegalDirective(format!(
+                        "pl-attrs can't set an attribute called {:?}",
+                        name
+                    )));
+                }
+                push_evaluated_attr(&mut evaluated, name, &v, AttrSource::Spread)?;
+            }
+        } else if let Some(name) = name.strip_prefix('^') {
+            let v = parse_eval(&val, vars)?;
+            if v == Value::Bool(false) && is_boolean_attribute(name) {
+                switched_off.push(name.to_owned());
+            }
+            push_evaluated_attr(&mut evaluated, name.to_owned(), &v, AttrSource::Dynamic)?;
+        } else {
+            evaluated.push((name, Some(val), AttrSource::Static));
+        }
+    }
+
+    // `class` and `style` are merged, otherwise `^` attributes win over
+    // regular attributes, which win over `pl-attrs`. A `null` or `false` doesn't
+    // replace a value from elsewhere, except a `^` boolean attribute set to `false`.
+    let mut merged: Vec<(String, Option<String>, AttrSource)> = vec![];
+
+    for (name, value, source) in evaluated {
+        match merged.iter_mut().find(|(n, _, _)| *n == name) {
+            None => merged.push((name, value, source)),
+            Some((_, existing, existing_source)) => {
+                if name == "class" || name == "style" {
+                    *existing = merge_attr_values(&name, existing.take(), value);
+                } else if existing.is_none() || (value.is_some() && source >= *existing_source) {
+                    *existing = value;
+                    *existing_source = source;
+                }
+            }
+        }
+    }
+
+    *attrs = merged
+        .into_iter()
+        .filter(|(name, _, _)| !switched_off.contains(name))
+        .filter_map(|(name, value, _)| value.map(|value| (name, value)))
+        .collect();
+
+    Ok(())
 }
 
+/// Removes the `<template pl-prop>` declarations at the top level of a template,
+/// checking `vars` against them and filling in defaults. Also returns the declared names.
+fn declare_props<FilesystemError>(
+    node: &mut Node,
+    vars: &Value,
+    filename: &String,
+    caller: Option<&String>,
+) -> Result<(Value, Vec<String>), RenderError<FilesystemError>> {
+    let Node::Document { children } = node else {
+        return Ok((vars.clone(), vec![]));
+    };
+
+    let mut vars = vars.clone();
+    let mut declared = vec![];
+    let mut i = 0;
+
+    while i < children.len() {
+        let attrs = match &children[i] {
+            Node::Element { name, attrs, .. }
+                if name == "pl-template" && attrs.iter().any(|(k, _)| k == "pl-prop") =>
+            {
+                attrs.clone()
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        children.remove(i);
+
+        let error = |e: String| RenderError {
+            kind: RenderErrorKind::InvalidProp(caller.cloned(), e),
+            filename: filename.to_owned(),
+        };
+
+        let mut prop = String::new();
+        let mut types = None;
+        let mut default = None;
+        for (k, v) in attrs {
+            match k.as_str() {
+                "pl-prop" => prop = v,
+                "pl-type" => {
+                    let parsed: Option<Vec<_>> =
+                        v.split('|').map(|t| Type::from_name(t.trim())).collect();
+                    types = Some(parsed.ok_or_else(|| error(format!("unknown type {:?}", v)))?);
+                }
+                "pl-default" => default = Some(v),
+                _ => {
+                    return Err(error(format!(
+                        "a pl-prop declaration can't have the attribute {:?}",
+                        k
+                    )))
+                }
+            }
+        }
+
+        let given = vars.get(&prop).cloned();
+        let val = match (given, default) {
+            (Some(val), _) => val,
+            (None, Some(default)) => parse_eval(&default, &vars).map_err(|e| RenderError {
+                kind: e,
+                filename: filename.to_owned(),
+            })?,
+            (None, None) => return Err(error(format!("missing required prop {:?}", prop))),
+        };
+
+        if let Some(types) = types {
+            let found = type_of(&val);
+            if !types.contains(&found) {
+                return Err(error(format!(
+                    "prop {:?} should be {}, found {}",
+                    prop,
+                    types
+                        .iter()
+                        .map(Type::to_string)
+                        .collect::<Vec<_>>()
+                        .join(" or "),
+                    found.to_string()
+                )));
+            }
+        }
+
+        if let Value::Object(o) = &mut vars {
+            o.insert(prop.clone(), val);
+        }
+        declared.push(prop);
+    }
+
+    Ok((vars, declared))
+}
+
+/// If the template has a top-level `<template pl-extends>`, returns the template
+/// it extends with this template's `pl-block` overrides applied, otherwise returns it unchanged.
+/// Also returns the file the returned template is from, as the overrides are marked with theirs.
+/// `chain` is the list of templates extended so far, to detect cycles.
+fn extend<FS, FileSystemError>(
+    node: Node,
+    filename: &String,
+    filesystem: &FS,
+    chain: &mut Vec<String>,
+) -> Result<(Node, String), RenderError<FileSystemError>>
+where
+    FS: Filesystem<FileSystemError>,
+    FileSystemError: fmt::Debug,
+{
+    let Node::Document { children } = &node else {
+        return Ok((node, filename.to_owned()));
+    };
+
+    let error = |kind| RenderError {
+        kind,
+        filename: filename.to_owned(),
+    };
+
+    let mut parent = None;
+    let mut overrides: HashMap<String, Vec<Node>> = HashMap::new();
+
+    for child in children {
+        match child {
+            Node::Element {
+                name,
+                attrs,
+                children,
+            } if name == "pl-template" => {
+                if let Some((_, src)) = attrs.iter().find(|(k, _)| k == "pl-extends") {
+                    if parent.replace(src).is_some() {
+                        return Err(error(RenderErrorKind::IllegalDirective(
+                            "a template can only have one pl-extends".into(),
+                        )));
+                    }
+                } else if let Some((_, block)) = attrs.iter().find(|(k, _)| k == "pl-block") {
+                    if overrides.insert(block.clone(), children.clone()).is_some() {
+                        return Err(error(RenderErrorKind::IllegalDirective(format!(
+                            "the block {:?} was overridden more than once",
+                            block
+                        ))));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let Some(src) = parent else {
+        return Ok((node, filename.to_owned()));
+    };
+
+    if children
+        .iter()
+        .any(|child| has_content(child) && !is_block_override(child))
+    {
+        return Err(error(RenderErrorKind::IllegalDirective(
+            "a template with pl-extends can only contain pl-block templates".into(),
+        )));
+    }
+
+    let path = filesystem
+        .move_to(filename, src)
+        .map_err(|e| error(RenderErrorKind::SrcNotFound(src.clone(), e)))?;
+
+    if chain.contains(&path) {
+        chain.push(path);
+        return Err(error(RenderErrorKind::ExtendsCycle(chain.clone())));
+    }
+    chain.push(path.clone());
+
+    let html = filesystem.read(&path).map_err(|e| RenderError {
+        kind: RenderErrorKind::FilesystemError(e),
+        filename: path.clone(),
+    })?;
+    let (mut parent, base) = extend(parse_html(html), &path, filesystem, chain)?;
+
+    apply_overrides(&mut parent, &mut overrides, filename, &base);
+
+    Ok((parent, base))
+}
+
+fn is_block_override(node: &Node) -> bool {
+    match node {
+        Node::Element { name, attrs, .. } => {
+            name == "pl-template"
+                && attrs
+                    .iter()
+                    .any(|(k, _)| k == "pl-extends" || k == "pl-block")
+        }
+        _ => false,
+    }
+}
+
+/// Replaces the content of each `pl-block` element with its override from `filename`, if there is one.
+/// Each override is used once, so an override can contain a block of the same name.
+/// `current` is the file `node` is from, so the content each override replaces stays marked with it.
+/// Blocks inside a `pl-raw` element are output as written, so aren't overridden.
+fn apply_overrides(
+    node: &mut Node,
+    overrides: &mut HashMap<String, Vec<Node>>,
+    filename: &String,
+    current: &String,
+) {
+    match node {
+        Node::Element {
+            name,
+            attrs,
+            children,
+        } => {
+            if attrs.iter().any(|(k, _)| k == "pl-raw") {
+                return;
+            }
+            let current = match name == ORIGIN_TAG {
+                true => &attrs[0].1,
+                false => current,
+            };
+            let block = attrs.iter().find(|(k, _)| k == "pl-block");
+            if let Some(new_children) = block.and_then(|(_, block)| overrides.remove(block)) {
+                let parent_content = from_file(current, children.clone());
+                let mut new_children = new_children;
+                for child in new_children.iter_mut() {
+                    replace_super(child, &parent_content);
+                }
+                *children = vec![from_file(filename, new_children)];
+            }
+            for child in children.iter_mut() {
+                apply_overrides(child, overrides, filename, current);
+            }
+        }
+        Node::Document { children } => {
+            for child in children.iter_mut() {
+                apply_overrides(child, overrides, filename, current);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Marks content as being from `filename`, for when templates are merged by `pl-extends`
+fn from_file(filename: &String, children: Vec<Node>) -> Node {
+    Node::Element {
+        name: ORIGIN_TAG.to_owned(),
+        attrs: vec![("file".to_owned(), filename.to_owned())],
+        children,
+    }
+}
+
+/// Replaces `pl-super` elements with the content of the block being overridden
+fn replace_super(node: &mut Node, parent_content: &Node) {
+    match node {
+        Node::Element { attrs, .. } if attrs.iter().any(|(k, _)| k == "pl-super") => {
+            *node = parent_content.clone();
+        }
+        Node::Element { children, .. } | Node::Document { children } => {
+            for child in children.iter_mut() {
+                replace_super(child, parent_content);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Removes the `<template pl-define>` and `<template pl-import>` components at the top level
+/// of a template, returning them
+fn define_components<FS, FilesystemError>(
+    node: &mut Node,
+    filename: &String,
+    filesystem: &FS,
+) -> Result<Components, RenderError<FilesystemError>>
+where
+    FS: Filesystem<FilesystemError>,
+{
+    let mut components = Components::new();
+
+    let Node::Document { children } = node else {
+        return Ok(components);
+    };
+
+    let error = |kind| RenderError {
+        kind,
+        filename: filename.to_owned(),
+    };
+
+    let mut i = 0;
+    while i < children.len() {
+        let (name, component) = match &children[i] {
+            Node::Element {
+                name,
+                attrs,
+                children: content,
+            } if name == "pl-template" => {
+                if let Some((_, name)) = attrs.iter().find(|(k, _)| k == "pl-define") {
+                    let content = match &content[..] {
+                        [Node::Document { children }] => children.clone(),
+                        _ => content.clone(),
+                    };
+                    (
+                        name.clone(),
+                        Component::Defined(content, filename.to_owned()),
+                    )
+                } else if let Some((_, name)) = attrs.iter().find(|(k, _)| k == "pl-import") {
+                    if !name.contains('-') {
+                        return Err(error(RenderErrorKind::IllegalDirective(format!(
+                            "pl-import names must contain a `-`, like custom elements, found {:?}",
+                            name
+                        ))));
+                    }
+                    let Some((_, src)) = attrs.iter().find(|(k, _)| k == "pl-src") else {
+                        return Err(error(RenderErrorKind::IllegalDirective(
+                            "pl-import needs a pl-src".into(),
+                        )));
+                    };
+                    let path = filesystem
+                        .move_to(filename, src)
+                        .map_err(|e| error(RenderErrorKind::SrcNotFound(src.clone(), e)))?;
+                    (name.clone(), Component::File(path))
+                } else {
+                    i += 1;
+                    continue;
+                }
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        if components.insert(name.clone(), component).is_some() {
+            return Err(error(RenderErrorKind::IllegalDirective(format!(
+                "the component {:?} was defined more than once",
+                name
+            ))));
+        }
+        children.remove(i);
+    }
+
+    Ok(components)
+}
+
+/// Renders a component declared with `pl-define` in `ctx.filename`, like `render` does for a file
+fn render_defined<FS, FileSystemError>(
+    nodes: Vec<Node>,
+    vars: &Value,
+    caller: &String,
+    ctx: &mut RenderContext<FS>,
+) -> Result<(Node, Vec<String>), RenderError<FileSystemError>>
+where
+    FS: Filesystem<FileSystemError>,
+    FileSystemError: fmt::Debug,
+{
+    let mut node = Node::Document { children: nodes };
+
+    let (vars, declared) = declare_props(&mut node, vars, ctx.filename, Some(caller))?;
+
+    let _ = render_elem(&mut node, &vars, &None, &mut None, ctx)?;
+
+    Ok((node, declared))
+}
+
+/// Renders a template file, returning it along with the names of the props it declares
 pub(crate) fn render<FS, FileSystemError>(
     vars: &Value,
-    slots: Rc<HashMap<String, Vec<Node>>>,
-    already_included_styles: &mut HashSet<(String, String)>,
+    slots: Rc<Slots>,
+    state: &mut RenderState,
     filename: &String,
+    caller: Option<&String>,
     filesystem: &FS,
-) -> Result<Node, RenderError<FileSystemError>>
+    options: &RenderOptions,
+) -> Result<(Node, Vec<String>), RenderError<FileSystemError>>
 where
     FS: Filesystem<FileSystemError>,
     FileSystemError: fmt::Debug,
@@ -540,16 +2068,27 @@ where
 
     let mut node = parse_html(html);
 
-    let _ = render_elem(
-        &mut node,
-        vars,
+    let mut components = define_components(&mut node, filename, filesystem)?;
+    let (vars, mut declared) = declare_props(&mut node, vars, filename, caller)?;
+
+    // props and components declared by the templates this one extends,
+    // which are relative to the template at the top of the chain
+    let (mut node, base) = extend(node, filename, filesystem, &mut vec![filename.clone()])?;
+    for (name, nodes) in define_components(&mut node, &base, filesystem)? {
+        components.entry(name).or_insert(nodes);
+    }
+    let (vars, inherited) = declare_props(&mut node, &vars, &base, caller)?;
+    declared.extend(inherited);
+
+    let mut ctx = RenderContext {
         slots,
-        already_included_styles,
-        &None,
-        &mut None,
-        filename,
+        components: Rc::new(components),
+        state,
+        filename: &base,
         filesystem,
-    )?;
+        options,
+    };
+    let _ = render_elem(&mut node, &vars, &None, &mut None, &mut ctx)?;
 
-    Ok(node)
+    Ok((node, declared))
 }
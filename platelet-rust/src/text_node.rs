@@ -20,12 +20,16 @@ pub(crate) fn render_text_node<'a>(
     txt: &'a str,
     vars: &Value,
 ) -> Result<Cow<'a, str>, RenderError> {
-    let hole_re = Regex::new(r"\{\{(.*?)\}\}").unwrap();
+    // `\{{` is a literal `{{`
+    let hole_re = Regex::new(r"\\\{\{|\{\{(.*?)\}\}").unwrap();
 
     let mut error = Ok(());
 
     let out = hole_re.replace_all(txt, |captures: &Captures| {
-        let exp_s = captures[1].to_string();
+        let Some(exp_s) = captures.get(1) else {
+            return "{{".to_owned();
+        };
+        let exp_s = exp_s.as_str().to_string();
         match expr(&mut exp_s.as_str()) {
             Ok(exp) => match eval(&exp, vars) {
                 Ok(s) => match stringify(&s) {
@@ -60,3 +64,18 @@ fn stringify(v: &Value) -> Result<String, Type> {
         Value::Object(_) => Err(Type::Object),
     }
 }
+
+/// Stringify a value for `pl-text`, which unlike `{{ }}` accepts any value
+pub(crate) fn stringify_text(v: &Value, separator: &str) -> String {
+    match v {
+        Value::Null => "".to_owned(),
+        Value::Array(a) => a
+            .iter()
+            .filter(|v| !v.is_null())
+            .map(|v| stringify_text(v, separator))
+            .collect::<Vec<_>>()
+            .join(separator),
+        Value::Object(_) => v.to_string(),
+        _ => stringify(v).unwrap_or_default(),
+    }
+}
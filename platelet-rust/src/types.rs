@@ -21,6 +21,18 @@ impl Type {
             Type::Null => "null",
         }
     }
+
+    pub(crate) fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "string" => Some(Type::String),
+            "number" => Some(Type::Number),
+            "object" => Some(Type::Object),
+            "array" => Some(Type::Array),
+            "bool" => Some(Type::Bool),
+            "null" => Some(Type::Null),
+            _ => None,
+        }
+    }
 }
 
 pub(crate) fn type_of(val: &Value) -> Type {
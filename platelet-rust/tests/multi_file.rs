@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
-use platelet::{render_with_custom_filesystem, renderer::Filesystem};
+use platelet::{
+    render_with_custom_filesystem, render_with_options,
+    renderer::{Filesystem, RenderError, RenderErrorKind, RenderOptions},
+};
 use serde_json::{json, Map};
 
 struct MockMultiFile {
@@ -9,7 +12,7 @@ struct MockMultiFile {
 
 impl Filesystem<()> for MockMultiFile {
     fn read(&self, path: &String) -> Result<String, ()> {
-        Ok(self.data.get(path).unwrap().clone())
+        self.data.get(path).cloned().ok_or(())
     }
     fn move_to(&self, _current: &String, path: &String) -> Result<String, ()> {
         Ok(path.to_owned())
@@ -91,6 +94,873 @@ fn pl_src_with_named_slots() {
     );
 }
 
+#[test]
+fn slot_default_content() {
+    let vars = Map::new().into();
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &vars,
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "index.html".into(),
+                    "<slot pl-src='card.html' ^title='\"Card\"'>\
+                       <template pl-slot='body'>filled</template>\
+                     </slot>"
+                        .to_owned(),
+                ),
+                (
+                    "card.html".into(),
+                    "<h2><slot pl-slot='title'>{{title}}</slot></h2>\
+                     <p><slot pl-slot='body'>default body</slot></p>\
+                     <div><slot pl-slot>nothing here</slot></div>"
+                        .to_owned(),
+                ),
+            ]),
+        },
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<h2>Card</h2><p>filled</p><div>nothing here</div>"
+    );
+}
+
+#[test]
+fn scoped_slot() {
+    let vars = json!({ "users": [{ "name": "ann" }, { "name": "bob" }], "greeting": "hi" });
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &vars,
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "index.html".into(),
+                    "<slot pl-src='list.html' ^rows='users'>\
+                       <template pl-slot='item' pl-slot-props='{ item }'>{{greeting}} {{item.name}}</template>\
+                     </slot>"
+                        .to_owned(),
+                ),
+                (
+                    "list.html".into(),
+                    "<ul><li pl-for='row in rows'><slot pl-slot='item' ^item='row'></slot></li></ul>"
+                        .to_owned(),
+                ),
+            ]),
+        },
+    );
+    assert_eq!(result.unwrap(), "<ul><li>hi ann</li><li>hi bob</li></ul>");
+}
+
+#[test]
+fn slot_fill_rendered_in_caller_scope() {
+    let vars = json!({ "name": "caller" });
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &vars,
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "index.html".into(),
+                    "<slot pl-src='card.html' ^name='\"card\"'><b pl-if='name'>{{name}}</b></slot>"
+                        .to_owned(),
+                ),
+                (
+                    "card.html".into(),
+                    "<div>{{name}}: <slot pl-slot></slot></div>".to_owned(),
+                ),
+            ]),
+        },
+    );
+    assert_eq!(result.unwrap(), "<div>card: <b>caller</b></div>");
+}
+
+#[test]
+fn conditional_slot_fills() {
+    let card = "<h2><slot pl-slot='title'>untitled</slot></h2>".to_owned();
+    let index = "<slot pl-src='card.html'>\
+                   <template pl-if='kind == \"a\"' pl-slot='title'>A</template>\
+                   <template pl-else-if='kind == \"b\"' pl-slot='title'>B</template>\
+                   <template pl-else pl-slot='title'>C</template>\
+                 </slot>"
+        .to_owned();
+    let fs = MockMultiFile {
+        data: HashMap::from([("index.html".into(), index), ("card.html".into(), card)]),
+    };
+
+    for (kind, expected) in [("a", "A"), ("b", "B"), ("z", "C")] {
+        let result =
+            render_with_custom_filesystem(&"index.html".into(), &json!({ "kind": kind }), &fs);
+        assert_eq!(result.unwrap(), format!("<h2>{}</h2>", expected));
+    }
+}
+
+#[test]
+fn skipped_slot_fill_uses_slot_content() {
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &json!({ "show": false }),
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "index.html".into(),
+                    "<slot pl-src='card.html'><template pl-if='show' pl-slot='title'>shown</template></slot>"
+                        .to_owned(),
+                ),
+                (
+                    "card.html".into(),
+                    "<h2><slot pl-slot='title'>untitled</slot></h2>".to_owned(),
+                ),
+            ]),
+        },
+    );
+    assert_eq!(result.unwrap(), "<h2>untitled</h2>");
+}
+
+#[test]
+fn looped_slot_fill_and_content() {
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &json!({ "xs": [1, 2, 3] }),
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "index.html".into(),
+                    "<slot pl-src='card.html'>\
+                       <template pl-for='x in xs' pl-slot='title'>{{x}}</template>\
+                       <i pl-for='x in xs'>{{x}}</i>\
+                       <template pl-if='xs'><b>!</b></template>\
+                     </slot>"
+                        .to_owned(),
+                ),
+                (
+                    "card.html".into(),
+                    "<h2><slot pl-slot='title'></slot></h2><slot pl-slot></slot>".to_owned(),
+                ),
+            ]),
+        },
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<h2>123</h2><i>1</i><i>2</i><i>3</i><b>!</b>"
+    );
+}
+
+#[test]
+fn slot_filled_twice() {
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &Map::new().into(),
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "index.html".into(),
+                    "<slot pl-src='card.html'>\
+                       <template pl-slot='title'>a</template>\
+                       <template pl-slot='title'>b</template>\
+                     </slot>"
+                        .to_owned(),
+                ),
+                (
+                    "card.html".into(),
+                    "<slot pl-slot='title'></slot>".to_owned(),
+                ),
+            ]),
+        },
+    );
+    match result {
+        Err(RenderError {
+            kind: RenderErrorKind::IllegalDirective(msg),
+            ..
+        }) => assert_eq!(msg, "the slot \"title\" was filled more than once"),
+        _ => panic!("expected an error, got {:?}", result),
+    }
+}
+
+#[test]
+fn has_slot() {
+    let card = "<article><slot pl-slot></slot></article>\
+                <footer pl-if='has_slot(\"footer\")'><slot pl-slot='footer'></slot></footer>"
+        .to_owned();
+    let fs = |index: &str| MockMultiFile {
+        data: HashMap::from([
+            ("index.html".into(), index.to_owned()),
+            ("card.html".into(), card.clone()),
+        ]),
+    };
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &Map::new().into(),
+        &fs("<slot pl-src='card.html'>body<template pl-slot='footer'>foot</template></slot>"),
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<article>body</article><footer>foot</footer>"
+    );
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &Map::new().into(),
+        &fs("<slot pl-src='card.html'>body</slot>"),
+    );
+    assert_eq!(result.unwrap(), "<article>body</article>");
+}
+
+#[test]
+fn prop_declarations() {
+    let card = "<template pl-prop='title' pl-type='string'></template>\
+                <template pl-prop='count' pl-type='number | null' pl-default='null'></template>\
+                <h2>{{title}}</h2><p pl-if='count != null'>{{count}}</p>"
+        .to_owned();
+    let fs = |index: &str| MockMultiFile {
+        data: HashMap::from([
+            ("index.html".into(), index.to_owned()),
+            ("card.html".into(), card.clone()),
+        ]),
+    };
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &Map::new().into(),
+        &fs("<slot pl-src='card.html' ^title='\"hi\"'></slot>"),
+    );
+    assert_eq!(result.unwrap(), "<h2>hi</h2>");
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &Map::new().into(),
+        &fs("<slot pl-src='card.html' ^title='\"hi\"' ^count='3'></slot>"),
+    );
+    assert_eq!(result.unwrap(), "<h2>hi</h2><p>3</p>");
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &Map::new().into(),
+        &fs("<slot pl-src='card.html'></slot>"),
+    );
+    assert_eq!(
+        result.unwrap_err(),
+        RenderError {
+            kind: RenderErrorKind::InvalidProp(
+                Some("index.html".into()),
+                "missing required prop \"title\"".into()
+            ),
+            filename: "card.html".into()
+        }
+    );
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &Map::new().into(),
+        &fs("<slot pl-src='card.html' ^title='1'></slot>"),
+    );
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "INVALID PROP: prop \"title\" should be string, found number (passed from index.html)\nin card.html"
+    );
+}
+
+#[test]
+fn static_attributes_as_props() {
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &Map::new().into(),
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "index.html".into(),
+                    "<slot pl-src='button.html' variant='primary' size='small' ^size='\"large\"'></slot>\
+                     <slot pl-src='button.html' ^variant='1 + 1' variant='primary'></slot>"
+                        .to_owned(),
+                ),
+                (
+                    "button.html".into(),
+                    "<button ^class='[variant, size]'>go</button>".to_owned(),
+                ),
+            ]),
+        },
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<button class='primary large'>go</button><button class='2'>go</button>"
+    );
+}
+
+#[test]
+fn fallthrough_attributes() {
+    let button = "<template pl-prop='label'></template>\
+                  <style>button { color: red }</style>\
+                  <button class='btn' style='padding: 0' type='button'>{{label}}</button>"
+        .to_owned();
+    let fs = |index: &str| MockMultiFile {
+        data: HashMap::from([
+            ("index.html".into(), index.to_owned()),
+            ("button.html".into(), button.clone()),
+        ]),
+    };
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &json!({ "big": true }),
+        &fs(
+            "<slot pl-src='button.html' pl-fallthrough label='go' id='cta' \
+                   ^class='{\"big\": big}' style='margin: 0' type='submit'></slot>",
+        ),
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<style>button { color: red }</style>\
+         <button class='btn big' style='padding: 0; margin: 0' type='submit' id='cta'>go</button>"
+    );
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &Map::new().into(),
+        &fs("<slot pl-src='button.html' label='go' id='cta'></slot>"),
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<style>button { color: red }</style>\
+         <button class='btn' style='padding: 0' type='button'>go</button>"
+    );
+}
+
+#[test]
+fn fallthrough_multiple_roots() {
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &Map::new().into(),
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "index.html".into(),
+                    "<slot pl-src='pair.html' pl-fallthrough class='x'></slot>".to_owned(),
+                ),
+                ("pair.html".into(), "<p>a</p> <p>b</p>".to_owned()),
+            ]),
+        },
+    );
+    assert_eq!(
+        result.unwrap_err(),
+        RenderError {
+            kind: RenderErrorKind::IllegalDirective(
+                "pl-fallthrough needs a component with exactly one root element, found 2".into()
+            ),
+            filename: "pair.html".into()
+        }
+    );
+}
+
+#[test]
+fn fallthrough_root_inside_template_or_component() {
+    let fs = |index: &str| MockMultiFile {
+        data: HashMap::from([
+            ("index.html".into(), index.to_owned()),
+            (
+                "conditional.html".into(),
+                "<template pl-if='true'><div class='b'></div></template>".to_owned(),
+            ),
+            (
+                "wrapper.html".into(),
+                "<slot pl-src='conditional.html'></slot>".to_owned(),
+            ),
+        ]),
+    };
+
+    for component in ["conditional.html", "wrapper.html"] {
+        let result = render_with_custom_filesystem(
+            &"index.html".into(),
+            &Map::new().into(),
+            &fs(&format!(
+                "<slot pl-src='{}' pl-fallthrough class='x'></slot>",
+                component
+            )),
+        );
+        assert_eq!(result.unwrap(), "<div class='b x'></div>");
+    }
+}
+
+#[test]
+fn extends_multiple_levels() {
+    let result = render_with_custom_filesystem(
+        &"page.html".into(),
+        &json!({ "title": "Post" }),
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "base.html".into(),
+                    "<title pl-block='title'>Site</title>\
+                     <main pl-block='content'>nothing</main>\
+                     <footer-nav><template pl-block='footer'>(c)</template></footer-nav>"
+                        .to_owned(),
+                ),
+                (
+                    "blog.html".into(),
+                    "<template pl-extends='base.html'></template>\
+                     <template pl-block='title'>Blog | <slot pl-super></slot></template>\
+                     <template pl-block='content'><article pl-block='post'>no post</article><aside>links</aside></template>"
+                        .to_owned(),
+                ),
+                (
+                    "page.html".into(),
+                    "<template pl-extends='blog.html'></template>\
+                     <template pl-block='title'>{{title}} | <slot pl-super></slot></template>\
+                     <template pl-block='post'><h1>{{title}}</h1></template>"
+                        .to_owned(),
+                ),
+            ]),
+        },
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<title>Post | Blog | Site</title>\
+         <main><article><h1>Post</h1></article><aside>links</aside></main>\
+         <footer-nav>(c)</footer-nav>"
+    );
+}
+
+#[test]
+fn extends_cycle() {
+    let result = render_with_custom_filesystem(
+        &"a.html".into(),
+        &Map::new().into(),
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "a.html".into(),
+                    "<template pl-extends='b.html'></template>".to_owned(),
+                ),
+                (
+                    "b.html".into(),
+                    "<template pl-extends='a.html'></template>".to_owned(),
+                ),
+            ]),
+        },
+    );
+    assert_eq!(
+        result.unwrap_err(),
+        RenderError {
+            kind: RenderErrorKind::ExtendsCycle(vec![
+                "a.html".into(),
+                "b.html".into(),
+                "a.html".into()
+            ]),
+            filename: "b.html".into()
+        }
+    );
+}
+
+#[test]
+fn extends_block_in_script() {
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &Map::new().into(),
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "base.html".into(),
+                    "<script pl-block='js'>var a;</script>".to_owned(),
+                ),
+                (
+                    "index.html".into(),
+                    "<template pl-extends='base.html'></template>\
+                     <template pl-block='js'>var b;</template>"
+                        .to_owned(),
+                ),
+            ]),
+        },
+    );
+    assert_eq!(result.unwrap(), "<script>var b;</script>");
+}
+
+#[test]
+fn extends_block_in_pl_raw() {
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &Map::new().into(),
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "base.html".into(),
+                    "<div pl-raw><p pl-block='r'>r</p></div>".to_owned(),
+                ),
+                (
+                    "index.html".into(),
+                    "<template pl-extends='base.html'></template>\
+                     <template pl-block='r'>x</template>"
+                        .to_owned(),
+                ),
+            ]),
+        },
+    );
+    assert_eq!(result.unwrap(), "<div><p pl-block='r'>r</p></div>");
+}
+
+#[test]
+fn component_registry_and_import() {
+    let fs = MockMultiFile {
+        data: HashMap::from([
+            (
+                "index.html".into(),
+                "<template pl-import='ui-badge' pl-src='badge.html'></template>\
+                 <ui-card ^title='\"Hi\"'><ui-badge label='new'></ui-badge> body</ui-card>\
+                 <other-tag>left alone</other-tag>"
+                    .to_owned(),
+            ),
+            (
+                "card.html".into(),
+                "<article><h2>{{title}}</h2><slot pl-slot></slot></article>".to_owned(),
+            ),
+            ("badge.html".into(), "<b>{{label}}</b>".to_owned()),
+        ]),
+    };
+
+    let result = render_with_options(
+        &"index.html".into(),
+        &Map::new().into(),
+        &fs,
+        &RenderOptions {
+            components: HashMap::from([("ui-card".into(), "card.html".into())]),
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<article><h2>Hi</h2><b>new</b> body</article><other-tag>left alone</other-tag>"
+    );
+
+    let result = render_with_options(
+        &"index.html".into(),
+        &Map::new().into(),
+        &fs,
+        &RenderOptions {
+            components: HashMap::from([("card".into(), "card.html".into())]),
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        result.unwrap_err(),
+        RenderError {
+            kind: RenderErrorKind::BadComponentName("card".into()),
+            filename: "index.html".into()
+        }
+    );
+}
+
+#[test]
+fn recursive_component() {
+    let vars = json!({ "tree": { "name": "a", "children": [
+        { "name": "b", "children": [{ "name": "c", "children": [] }] },
+        { "name": "d", "children": [] }
+    ] } });
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &vars,
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "index.html".into(),
+                    "<slot pl-src='node.html' ^node='tree'></slot>".to_owned(),
+                ),
+                (
+                    "node.html".into(),
+                    "<li>{{node.name}}<ul pl-if='node.children'>\
+                       <slot pl-for='child in node.children' pl-src='node.html' ^node='child'></slot>\
+                     </ul></li>"
+                        .to_owned(),
+                ),
+            ]),
+        },
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<li>a<ul><li>b<ul><li>c</li></ul></li><li>d</li></ul></li>"
+    );
+}
+
+#[test]
+fn include_cycle() {
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &Map::new().into(),
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "index.html".into(),
+                    "<slot pl-src='a.html' ^n='1'></slot>".to_owned(),
+                ),
+                (
+                    "a.html".into(),
+                    "<slot pl-src='b.html' ^n='n'></slot>".to_owned(),
+                ),
+                (
+                    "b.html".into(),
+                    "<slot pl-src='a.html' ^n='n'></slot>".to_owned(),
+                ),
+            ]),
+        },
+    );
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "CYCLIC INCLUDE: index.html -> a.html -> b.html -> a.html\nin b.html"
+    );
+}
+
+#[test]
+fn max_depth() {
+    let fs = MockMultiFile {
+        data: HashMap::from([
+            (
+                "index.html".into(),
+                "<slot pl-src='count.html' ^n='0'></slot>".to_owned(),
+            ),
+            (
+                "count.html".into(),
+                "{{n}}<slot pl-if='n < 3' pl-src='count.html' ^n='n + 1'></slot>".to_owned(),
+            ),
+        ]),
+    };
+
+    let result = render_with_custom_filesystem(&"index.html".into(), &Map::new().into(), &fs);
+    assert_eq!(result.unwrap(), "0123");
+
+    let result = render_with_options(
+        &"index.html".into(),
+        &Map::new().into(),
+        &fs,
+        &RenderOptions {
+            max_depth: 2,
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        result.unwrap_err(),
+        RenderError {
+            kind: RenderErrorKind::MaxDepthExceeded(
+                2,
+                vec![
+                    "index.html".into(),
+                    "count.html".into(),
+                    "count.html".into(),
+                    "count.html".into()
+                ]
+            ),
+            filename: "count.html".into()
+        }
+    );
+}
+
+#[test]
+fn directive_pairs_with_pl_src() {
+    let vars = json!({ "t": true, "f": false, "xs": [1, 2], "s": "b", "h": "<i>hi</i>" });
+
+    let cases: Vec<(&str, Result<&str, &str>)> = vec![
+        (
+            "<slot pl-if='t' pl-src='c.html' ^n='0'></slot>",
+            Ok("<b>0</b>"),
+        ),
+        (
+            "<p pl-if='f'>a</p><slot pl-else-if='t' pl-src='c.html' ^n='0'></slot>",
+            Ok("<b>0</b>"),
+        ),
+        (
+            "<p pl-if='f'>a</p><slot pl-else pl-src='c.html' ^n='0'></slot>",
+            Ok("<b>0</b>"),
+        ),
+        (
+            "<slot pl-for='x in xs' pl-src='c.html' ^n='x'></slot>",
+            Ok("<b>1</b><b>2</b>"),
+        ),
+        (
+            "<slot pl-is='\"div\"' pl-src='c.html' ^n='0'></slot>",
+            Err("pl-is has no effect alongside pl-src"),
+        ),
+        (
+            "<slot pl-switch='s' pl-src='c.html' ^n='0'></slot>",
+            Err("pl-switch and pl-src can't be used on the same element"),
+        ),
+        (
+            "<slot pl-text='s' pl-src='c.html' ^n='0'></slot>",
+            Err("pl-text and pl-src can't be used on the same element"),
+        ),
+        (
+            "<slot pl-html='h' pl-src='c.html' ^n='0'></slot>",
+            Err("pl-html and pl-src can't be used on the same element"),
+        ),
+        (
+            "<slot pl-attrs='{}' pl-src='c.html' ^n='0'></slot>",
+            Err("pl-attrs has no effect alongside pl-src"),
+        ),
+        (
+            "<slot pl-slot pl-src='c.html' ^n='0'></slot>",
+            Err("pl-src and pl-slot can't be used on the same element"),
+        ),
+        (
+            "<div pl-switch='s'><slot pl-case='\"b\"' pl-src='c.html' ^n='0'></slot></div>",
+            Ok("<div><b>0</b></div>"),
+        ),
+        (
+            "<div pl-switch='s'><slot pl-default pl-src='c.html' ^n='0'></slot></div>",
+            Ok("<div><b>0</b></div>"),
+        ),
+        (
+            "<slot pl-raw pl-src='c.html' ^n='0'></slot>",
+            Err("pl-src has no effect alongside pl-raw"),
+        ),
+        (
+            "<slot pl-optional pl-src='c.html' ^n='0'></slot>",
+            Ok("<b>0</b>"),
+        ),
+        (
+            "<slot pl-fallthrough pl-src='c.html' id='z' ^n='0'></slot>",
+            Ok("<b id='z'>0</b>"),
+        ),
+        // pl-optional, which only works alongside pl-src
+        (
+            "<p pl-optional pl-if='t'>x</p>",
+            Err("pl-optional only works alongside pl-src"),
+        ),
+        (
+            "<slot pl-optional pl-if='t' pl-src='missing.html'>fallback</slot>",
+            Ok("fallback"),
+        ),
+        (
+            "<p pl-if='f'>a</p><slot pl-optional pl-else-if='t' pl-src='missing.html'>fallback</slot>",
+            Ok("fallback"),
+        ),
+        (
+            "<p pl-if='f'>a</p><slot pl-optional pl-else pl-src='missing.html'>fallback</slot>",
+            Ok("fallback"),
+        ),
+        (
+            "<slot pl-optional pl-for='x in xs' pl-src='missing.html'>{{x}}</slot>",
+            Ok("12"),
+        ),
+        (
+            "<slot pl-optional pl-is='\"div\"' pl-src='c.html' ^n='0'></slot>",
+            Err("pl-is has no effect alongside pl-src"),
+        ),
+        (
+            "<slot pl-optional pl-switch='s' pl-src='c.html' ^n='0'></slot>",
+            Err("pl-switch and pl-src can't be used on the same element"),
+        ),
+        (
+            "<slot pl-optional pl-text='s' pl-src='c.html' ^n='0'></slot>",
+            Err("pl-text and pl-src can't be used on the same element"),
+        ),
+        (
+            "<slot pl-optional pl-html='h' pl-src='c.html' ^n='0'></slot>",
+            Err("pl-html and pl-src can't be used on the same element"),
+        ),
+        (
+            "<slot pl-optional pl-attrs='{}' pl-src='c.html' ^n='0'></slot>",
+            Err("pl-attrs has no effect alongside pl-src"),
+        ),
+        (
+            "<slot pl-optional pl-slot pl-src='c.html' ^n='0'></slot>",
+            Err("pl-src and pl-slot can't be used on the same element"),
+        ),
+        (
+            "<div pl-switch='s'><slot pl-optional pl-case='\"b\"' pl-src='missing.html'>fallback</slot></div>",
+            Ok("<div>fallback</div>"),
+        ),
+        (
+            "<div pl-switch='s'><slot pl-optional pl-default pl-src='missing.html'>fallback</slot></div>",
+            Ok("<div>fallback</div>"),
+        ),
+        (
+            "<slot pl-optional pl-raw pl-src='c.html' ^n='0'></slot>",
+            Err("pl-optional has no effect alongside pl-raw"),
+        ),
+        (
+            "<slot pl-optional pl-use='c'></slot>",
+            Err("pl-optional only works alongside pl-src"),
+        ),
+        (
+            "<slot pl-src='c.html' ^pl-src='\"d.html\"' ^n='0'></slot>",
+            Err("pl-src and ^pl-src can't be used on the same element"),
+        ),
+        (
+            "<slot pl-src='c.html' pl-use='c' ^n='0'></slot>",
+            Err("pl-src and pl-use can't be used on the same element"),
+        ),
+        (
+            "<slot ^pl-src='\"c.html\"' pl-use='c' ^n='0'></slot>",
+            Err("^pl-src and pl-use can't be used on the same element"),
+        ),
+        (
+            "<slot pl-optional pl-fallthrough pl-src='c.html' id='z' ^n='0'></slot>",
+            Ok("<b id='z'>0</b>"),
+        ),
+    ];
+
+    for (template, expected) in cases {
+        let result = render_with_custom_filesystem(
+            &"index.html".into(),
+            &vars,
+            &MockMultiFile {
+                data: HashMap::from([
+                    ("index.html".into(), template.to_owned()),
+                    (
+                        "c.html".into(),
+                        "<template pl-prop='n'></template><b>{{n}}</b>".to_owned(),
+                    ),
+                ]),
+            },
+        );
+
+        match expected {
+            Ok(html) => assert_eq!(result, Ok(html.to_owned()), "{}", template),
+            Err(message) => assert_eq!(
+                result,
+                Err(RenderError {
+                    kind: RenderErrorKind::IllegalDirective(message.into()),
+                    filename: "index.html".to_owned()
+                }),
+                "{}",
+                template
+            ),
+        }
+    }
+}
+
+#[test]
+fn strict_slots() {
+    let vars = Map::new().into();
+
+    let result = render_with_options(
+        &"index.html".into(),
+        &vars,
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "index.html".into(),
+                    "<slot pl-src='card.html'></slot>".to_owned(),
+                ),
+                (
+                    "card.html".into(),
+                    "<p><slot pl-slot='body'>default body</slot></p>".to_owned(),
+                ),
+            ]),
+        },
+        &RenderOptions {
+            strict_slots: true,
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        result.unwrap_err(),
+        RenderError {
+            kind: RenderErrorKind::UndefinedSlot("body".to_owned()),
+            filename: "card.html".to_owned(),
+        }
+    );
+}
+
 #[test]
 fn pl_src_with_cotext() {
     let vars = Map::new().into();
@@ -131,6 +1001,109 @@ fn pl_for_and_pl_src() {
     assert_eq!(result.unwrap(), "<h1>1</h1><h1>2</h1><h1>3</h1>");
 }
 
+#[test]
+fn dynamic_pl_src() {
+    let vars = json!({ "blocks": [
+        { "type": "text", "body": "hello" },
+        { "type": "quote", "body": "world" }
+    ] });
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &vars,
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "index.html".into(),
+                    "<slot pl-for='block in blocks' ^pl-src='\"blocks/\" + block.type + \".html\"' ^block='block'></slot>"
+                        .to_owned(),
+                ),
+                ("blocks/text.html".into(), "<p>{{block.body}}</p>".to_owned()),
+                (
+                    "blocks/quote.html".into(),
+                    "<blockquote>{{block.body}}</blockquote>".to_owned(),
+                ),
+            ]),
+        },
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<p>hello</p><blockquote>world</blockquote>"
+    );
+}
+
+#[test]
+fn dynamic_pl_src_not_found() {
+    let vars = json!({ "block": { "type": "video" } });
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &vars,
+        &MockMultiFile {
+            data: HashMap::from([(
+                "index.html".into(),
+                "<slot ^pl-src='\"blocks/\" + block.type + \".html\"'></slot>".to_owned(),
+            )]),
+        },
+    );
+    let error = result.unwrap_err();
+    assert_eq!(
+        error,
+        RenderError {
+            kind: RenderErrorKind::FilesystemError(()),
+            filename: "blocks/video.html".to_owned(),
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "FILE SYSTEM ERROR: ()\nin blocks/video.html"
+    );
+}
+
+#[test]
+fn optional_pl_src_missing() {
+    let vars = json!({ "tenant": "acme", "name": "world" });
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &vars,
+        &MockMultiFile {
+            data: HashMap::from([(
+                "index.html".into(),
+                "<nav><slot ^pl-src='tenant + \"/header.html\"' pl-optional>\
+                   <h1>hello {{name}}</h1>\
+                   <template pl-slot='extra'>not this</template>\
+                 </slot></nav>"
+                    .to_owned(),
+            )]),
+        },
+    );
+    assert_eq!(result.unwrap(), "<nav><h1>hello world</h1></nav>");
+}
+
+#[test]
+fn optional_pl_src_present() {
+    let vars = json!({ "tenant": "acme" });
+
+    let result = render_with_custom_filesystem(
+        &"index.html".into(),
+        &vars,
+        &MockMultiFile {
+            data: HashMap::from([
+                (
+                    "index.html".into(),
+                    "<nav><slot ^pl-src='tenant + \"/header.html\"' pl-optional>\
+                       <h1>default</h1>\
+                     </slot></nav>"
+                        .to_owned(),
+                ),
+                ("acme/header.html".into(), "<h1>ACME</h1>".to_owned()),
+            ]),
+        },
+    );
+    assert_eq!(result.unwrap(), "<nav><h1>ACME</h1></nav>");
+}
+
 #[test]
 fn example() {
     let vars = json!({
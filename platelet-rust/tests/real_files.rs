@@ -1,6 +1,6 @@
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 
-use platelet::render_file;
+use platelet::{render_file, render_with_options, renderer::RenderOptions, PathFilesystem};
 use serde_json::json;
 
 #[test]
@@ -24,3 +24,39 @@ fn pl_src() {
 </body></html>"#
     );
 }
+
+#[test]
+fn pl_extends_layout_in_another_directory() {
+    let result = render_file(
+        Path::new("./tests/extends/pages/home.html"),
+        &json!({ "title": "Home" }),
+    );
+
+    assert_eq!(
+        result.unwrap(),
+        "\n<div><nav>layout nav</nav>\n</div>\n\
+         <main><h1>Home</h1><section>page hero</section>\n<footer>layout footer</footer>\n</main>\n"
+    );
+
+    let result = render_file(Path::new("./tests/extends/pages/home.html"), &json!({}));
+
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "INVALID PROP: missing required prop \"title\"\nin ./tests/extends/pages/../layouts/base.html"
+    );
+}
+
+#[test]
+fn unused_registered_component_missing() {
+    let result = render_with_options(
+        &"./tests/example_index.html".into(),
+        &json!({}),
+        &PathFilesystem {},
+        &RenderOptions {
+            components: HashMap::from([("ui-gone".into(), "gone.html".into())]),
+            ..Default::default()
+        },
+    );
+
+    assert!(result.is_ok());
+}
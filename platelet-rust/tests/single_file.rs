@@ -1,7 +1,6 @@
 use platelet::{
     render,
     renderer::{RenderError, RenderErrorKind},
-    types,
 };
 use serde_json::{json, Map};
 
@@ -157,6 +156,44 @@ fn pl_html_with_template() {
     assert_eq!(result.unwrap(), "<p>hello world</p>");
 }
 
+#[test]
+fn pl_text() {
+    let vars = json!({ "title": "Tom & Jerry }} <3" });
+
+    let result = render(
+        "<title pl-text='title'>placeholder {{ oops }}</title>".into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<title>Tom &amp; Jerry }} &lt;3</title>");
+}
+
+#[test]
+fn pl_text_is_not_interpolated() {
+    let vars = json!({ "x": "{{ y }}", "y": "no" });
+
+    let result = render("<p pl-text='x'></p>".into(), &vars);
+    assert_eq!(result.unwrap(), "<p>{{ y }}</p>");
+}
+
+#[test]
+fn pl_text_null_and_arrays() {
+    let vars = json!({ "tags": ["a", null, "b"] });
+
+    let result = render(
+        "<p pl-text='null'></p><p pl-text='tags'></p><p pl-text='tags' pl-join=' / '></p>".into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<p></p><p>a, b</p><p>a / b</p>");
+}
+
+#[test]
+fn pl_text_object() {
+    let vars = json!({ "o": { "a": 1 } });
+
+    let result = render("<option ^value='o.a' pl-text='o'></option>".into(), &vars);
+    assert_eq!(result.unwrap(), r#"<option value='1'>{"a":1}</option>"#);
+}
+
 #[test]
 fn template_preserved() {
     let vars = Map::new().into();
@@ -176,6 +213,42 @@ fn pl_for() {
     assert_eq!(result.unwrap(), "<div><p>1</p><p>2</p><p>3</p></div>");
 }
 
+#[test]
+fn pl_for_else_empty() {
+    let vars = json!({ "items": [] });
+
+    let result = render(
+        "<ul><li pl-for='x in items'>{{x}}</li><li pl-else>no results</li></ul>".into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<ul><li>no results</li></ul>");
+}
+
+#[test]
+fn pl_for_else_not_empty() {
+    let vars = json!({ "items": [1, 2] });
+
+    let result = render(
+        "<ul><li pl-for='x in items'>{{x}}</li><li pl-else>no results</li></ul>".into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<ul><li>1</li><li>2</li></ul>");
+}
+
+#[test]
+fn pl_for_else_if_empty() {
+    let vars = json!({ "items": [], "loading": true });
+
+    let result = render(
+        "<p pl-for='x in items'>{{x}}</p>\
+         <p pl-else-if='loading'>loading...</p>\
+         <p pl-else>no results</p>"
+            .into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<p>loading...</p>");
+}
+
 #[test]
 fn pl_for_template() {
     let vars = Map::new().into();
@@ -229,6 +302,104 @@ fn pl_else_false() {
     assert_eq!(result.unwrap(), "<p>B</p>");
 }
 
+#[test]
+fn pl_switch() {
+    let vars = json!({ "status": "published" });
+
+    let result = render(
+        "<div pl-switch='status'>\
+           <p pl-case='\"draft\"'>Draft</p>\
+           <p pl-case='\"published\"'>Published</p>\
+           <p pl-default>Unknown</p>\
+         </div>"
+            .into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<div><p>Published</p></div>");
+}
+
+#[test]
+fn pl_switch_default() {
+    let vars = json!({ "status": "archived" });
+
+    let result = render(
+        "<template pl-switch='status'>\
+           <p pl-case='\"draft\"'>Draft</p>\
+           <template pl-default>Unknown</template>\
+         </template>"
+            .into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "Unknown");
+}
+
+#[test]
+fn pl_switch_first_match_wins() {
+    let vars = json!({ "n": 2 });
+
+    let result = render(
+        "<div pl-switch='n'><p pl-case='2.0'>A</p><p pl-case='1 + 1'>B</p></div>".into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<div><p>A</p></div>");
+}
+
+#[test]
+fn pl_case_outside_pl_switch() {
+    let vars = Map::new().into();
+
+    let result = render("<p pl-case='1'>A</p>".into(), &vars);
+    assert_eq!(
+        result.unwrap_err(),
+        RenderError {
+            filename: "input".to_owned(),
+            kind: RenderErrorKind::IllegalDirective(
+                "encountered a pl-case or pl-default that wasn't a child of a pl-switch".into()
+            )
+        }
+    );
+}
+
+#[test]
+fn pl_case_after_pl_default() {
+    let vars = Map::new().into();
+
+    let result = render(
+        "<div pl-switch='1'><p pl-default>A</p><p pl-case='1'>B</p></div>".into(),
+        &vars,
+    );
+    assert_eq!(
+        result.unwrap_err(),
+        RenderError {
+            filename: "input".to_owned(),
+            kind: RenderErrorKind::IllegalDirective(
+                "encountered a pl-case after a pl-default".into()
+            )
+        }
+    );
+}
+
+#[test]
+fn pl_case_with_pl_if() {
+    let vars = Map::new().into();
+
+    let result = render(
+        "<div pl-switch='1'><i pl-case='1' pl-if='false'>A</i><i pl-default>B</i></div>".into(),
+        &vars,
+    );
+    assert_eq!(
+        result.unwrap_err(),
+        RenderError {
+            filename: "input".to_owned(),
+            kind: RenderErrorKind::IllegalDirective(
+                "pl-if can't be used on a pl-case or pl-default, \
+                 add the condition to the pl-case instead"
+                    .into()
+            )
+        }
+    );
+}
+
 #[test]
 fn caret_attr_eval() {
     let vars = Map::new().into();
@@ -275,6 +446,162 @@ fn caret_attr_object() {
     );
 }
 
+#[test]
+fn pl_attrs() {
+    let vars = json!({ "attrs": { "id": "main", "aria-label": "Main", "hidden": false, "data-x": null, "rel": ["a", "b"] } });
+
+    let result = render("<nav pl-attrs='attrs'></nav>".into(), &vars);
+    assert_eq!(
+        result.unwrap(),
+        "<nav id='main' aria-label='Main' rel='a b'></nav>"
+    );
+}
+
+#[test]
+fn pl_attrs_explicit_attributes_win() {
+    let vars = json!({ "attrs": { "id": "spread", "title": "spread", "lang": "spread" } });
+
+    let result = render(
+        "<p id='static' pl-attrs='attrs' ^title='\"dynamic\"' ^lang='null'></p>".into(),
+        &vars,
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<p id='static' title='dynamic' lang='spread'></p>"
+    );
+}
+
+#[test]
+fn pl_attrs_bad_name() {
+    let vars = json!({ "attrs": { "onclick='x'": "y" } });
+
+    let result = render("<p pl-attrs='attrs'></p>".into(), &vars);
+    assert_eq!(
+        result.unwrap_err(),
+        RenderError {
+            filename: "input".to_owned(),
+            kind: RenderErrorKind::IllegalDirective(
+                "pl-attrs can't set an attribute called \"onclick='x'\"".into()
+            )
+        }
+    );
+}
+
+#[test]
+fn caret_class_merged_with_static_class() {
+    let vars = json!({ "classes": { "active": true, "text-danger": true, "static": true } });
+
+    let result = render(
+        "<div class='static' ^class='classes' ^name='null'></div>".into(),
+        &vars,
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<div class='static active text-danger'></div>"
+    );
+}
+
+#[test]
+fn caret_style_object() {
+    let vars = json!({ "c": "red", "s": "12px" });
+
+    let result = render(
+        "<p style='margin: 0;' ^style='{\"color\": c}' pl-attrs='{\"style\": [{\"font-size\": s}]}'></p>"
+            .into(),
+        &vars,
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<p style='margin: 0; color: red; font-size: 12px'></p>"
+    );
+}
+
+#[test]
+fn caret_attr_overrides_static_attr() {
+    let vars = Map::new().into();
+
+    let result = render(
+        "<input type='text' value='a' ^value='\"b\"' ^type='null'>".into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<input type='text' value='b'>");
+}
+
+#[test]
+fn caret_attr_null_keeps_static_attr() {
+    let vars = Map::new().into();
+
+    let result = render(
+        "<input ^value='null' value='a' ^hidden='null' hidden ^title='false' title='t'>".into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<input value='a' hidden title='t'>");
+}
+
+#[test]
+fn caret_false_removes_static_boolean_attr() {
+    let vars = json!({ "off": false });
+
+    let result = render(
+        "<input disabled ^disabled='false'><input ^checked='off' checked>".into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<input><input>");
+}
+
+#[test]
+fn boolean_attributes() {
+    let vars = json!({ "yes": true, "no": false });
+
+    let result = render(
+        "<input ^disabled='yes' ^checked='no' ^required='1 == 1' readonly>".into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<input disabled required readonly>");
+}
+
+#[test]
+fn boolean_attribute_string_value() {
+    let vars = Map::new().into();
+
+    let result = render("<div ^hidden='\"until-found\"'></div>".into(), &vars);
+    assert_eq!(result.unwrap(), "<div hidden='until-found'></div>");
+}
+
+#[test]
+fn aria_attributes() {
+    let vars = json!({ "open": false });
+
+    let result = render(
+        "<button ^aria-expanded='open' ^aria-pressed='!open' ^data-open='str(open)'></button>"
+            .into(),
+        &vars,
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<button aria-expanded='false' aria-pressed='true' data-open='false'></button>"
+    );
+}
+
+#[test]
+fn caret_data_object() {
+    let vars = json!({ "block": { "id": 3, "kind": "promo", "userId": "u1", "tags": ["a", "b"], "active": false, "skip": null, "Size": "lg" } });
+
+    let result = render("<div ^data='block'></div>".into(), &vars);
+    assert_eq!(
+        result.unwrap(),
+        r#"<div data-id='3' data-kind='promo' data-user-id='u1' data-tags='["a","b"]' data-active='false' data-size='lg'></div>"#
+    );
+}
+
+#[test]
+fn caret_data_string_is_not_expanded() {
+    let vars = Map::new().into();
+
+    let result = render("<object ^data='\"movie.swf\"'></object>".into(), &vars);
+    assert_eq!(result.unwrap(), "<object data='movie.swf'></object>");
+}
+
 #[test]
 fn comments_uneffected() {
     let vars = Map::new().into();
@@ -338,6 +665,124 @@ fn for_loop_kvi() {
     );
 }
 
+#[test]
+fn for_loop_metadata() {
+    let vars = json!({ "names": ["Ann", "Bob", "Cy"] });
+
+    let result = render(
+        "<p><template pl-for='n in names'>{{n}}\
+         <template pl-if='loop.index1 == loop.length - 1'> and </template>\
+         <template pl-else-if='!loop.last'>, </template></template></p>"
+            .into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<p>Ann, Bob and Cy</p>");
+}
+
+#[test]
+fn for_loop_metadata_parity() {
+    let vars = Map::new().into();
+
+    let result = render(
+        "<tr pl-for='x in [1, 2, 3]' ^class='[loop.even && \"even\", loop.odd && \"odd\", loop.first && \"first\"]'></tr>"
+            .into(),
+        &vars,
+    );
+    assert_eq!(
+        result.unwrap(),
+        "<tr class='even first'></tr><tr class='odd'></tr><tr class='even'></tr>"
+    );
+}
+
+#[test]
+fn for_loop_metadata_parent() {
+    let vars = Map::new().into();
+
+    let result = render(
+        "<template pl-for='row in [1, 2]'><template pl-for='col in [1, 2]'>\
+         {{loop.parent.index}}{{loop.index}} \
+         </template></template>"
+            .into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "00 01 10 11 ");
+}
+
+#[test]
+fn for_loop_keeps_loop_variable() {
+    let vars = json!({ "loop": "mine" });
+
+    let result = render(
+        "<template pl-for='x in [1, 2]'><template pl-for='y in [3]'>{{loop}} </template></template>"
+            .into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "mine mine ");
+}
+
+#[test]
+fn for_loop_guard() {
+    let vars = json!({ "products": [
+        { "name": "apple", "in_stock": true },
+        { "name": "pear", "in_stock": false },
+        { "name": "plum", "in_stock": true }
+    ] });
+
+    let result = render(
+        "<li pl-for='p in products if p.in_stock' ^class='loop.last && \"last\"'>{{p.name}}</li>"
+            .into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<li>apple</li><li class='last'>plum</li>");
+}
+
+#[test]
+fn for_loop_object_destructuring() {
+    let vars = json!({ "products": [
+        { "name": "apple", "price": 3 },
+        { "name": "pear", "price": 5 }
+    ] });
+
+    let result = render(
+        "<li pl-for='({ name, price: cost }) in products'>{{name}}: {{cost}}</li>".into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<li>apple: 3</li><li>pear: 5</li>");
+}
+
+#[test]
+fn for_loop_array_destructuring() {
+    let vars = json!({ "pairs": [["a", 1], ["b", 2]] });
+
+    let result = render(
+        "<li pl-for='([k, v], i) in pairs'>{{i}}. {{k}} = {{v}}</li>".into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<li>0. a = 1</li><li>1. b = 2</li>");
+}
+
+#[test]
+fn for_loop_destructuring_type_mismatch() {
+    let vars = json!({ "pairs": [1, 2] });
+
+    let result = render("<li pl-for='[a, b] in pairs'></li>".into(), &vars);
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "FOR LOOP EVALUATION ERROR: Expected array, found number\nin input"
+    );
+}
+
+#[test]
+fn for_loop_malformed_pattern() {
+    let vars = Map::new().into();
+
+    let result = render("<li pl-for='[a, ] in pairs'></li>".into(), &vars);
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "FOR LOOP PARSER ERROR:\n[a, ] in pairs\n  ^\ninvalid destructuring pattern\nin input"
+    );
+}
+
 #[test]
 fn for_loop_if_else_if() {
     let vars = Map::new().into();
@@ -498,11 +943,648 @@ fn undefined_render_fail() {
 
     let result = render(r#"~{{ z }}!"#.into(), &vars);
 
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "TEXT RENDER ERROR: RenderError(Null)\nin input"
+    );
+}
+
+#[test]
+fn pl_define_and_use() {
+    let vars = json!({ "tags": ["new", "hot"] });
+
+    let result = render(
+        r#"<template pl-define="badge"><template pl-prop="label" pl-type="string"></template><b ^class="label">{{label}}<slot pl-slot></slot></b></template><p><slot pl-for="tag in tags" pl-use="badge" ^label="tag">!</slot></p>"#
+            .into(),
+        &vars,
+    );
+
+    assert_eq!(
+        result.unwrap(),
+        "<p><b class='new'>new!</b><b class='hot'>hot!</b></p>"
+    );
+}
+
+#[test]
+fn pl_use_undefined() {
+    let vars = Map::new().into();
+
+    let result = render(r#"<slot pl-use="badge"></slot>"#.into(), &vars);
+
+    assert_eq!(
+        result.unwrap_err(),
+        RenderError {
+            kind: RenderErrorKind::UndefinedComponent("badge".into()),
+            filename: "input".to_owned()
+        }
+    );
+}
+
+#[test]
+fn unknown_directive() {
+    let vars = json!({ "x": true });
+
+    for (template, message) in [
+        (
+            r#"<p pl-fi="x">hi</p>"#,
+            r#"unknown directive "pl-fi", did you mean "pl-if"?"#,
+        ),
+        (
+            r#"<p pl-if="x">a</p><p pl-esle>b</p>"#,
+            r#"unknown directive "pl-esle", did you mean "pl-else"?"#,
+        ),
+        (
+            r#"<slot ^pl-scr="'a.html'"></slot>"#,
+            r#"unknown directive "^pl-scr", did you mean "^pl-src"?"#,
+        ),
+        (
+            r#"<p pl-whatever>hi</p>"#,
+            r#"unknown directive "pl-whatever""#,
+        ),
+    ] {
+        let result = render(template.into(), &vars);
+
+        assert_eq!(
+            result.unwrap_err(),
+            RenderError {
+                kind: RenderErrorKind::IllegalDirective(message.into()),
+                filename: "input".to_owned()
+            }
+        );
+    }
+}
+
+#[test]
+fn misplaced_directive() {
+    let vars = json!({ "x": "hi" });
+
+    for (template, message) in [
+        (
+            r#"<div pl-slot="a"></div>"#,
+            "pl-slot only works on a <slot> or a <template>",
+        ),
+        (
+            r#"<p pl-join=" ">{{x}}</p>"#,
+            "pl-join only works alongside pl-text",
+        ),
+        (
+            r#"<p pl-optional>hi</p>"#,
+            "pl-optional only works alongside pl-src",
+        ),
+        (
+            r#"<p pl-fallthrough class="a">hi</p>"#,
+            "pl-fallthrough only works on a component",
+        ),
+        (
+            r#"<div><template pl-prop="x"></template></div>"#,
+            "pl-prop only works on a <template> at the top level of a template",
+        ),
+        (
+            r#"<div><template pl-prop="x" pl-default="1"></template></div>"#,
+            "pl-prop only works on a <template> at the top level of a template",
+        ),
+        (
+            r#"<template pl-slot="a" pl-slot-props="{ y }"></template>"#,
+            "pl-slot-props only works on a <template pl-slot> filling a component's slot",
+        ),
+        (
+            r#"<p>a <slot pl-super></slot></p>"#,
+            "pl-super only works inside a pl-block override",
+        ),
+    ] {
+        let result = render(template.into(), &vars);
+
+        assert_eq!(
+            result.unwrap_err(),
+            RenderError {
+                kind: RenderErrorKind::IllegalDirective(message.into()),
+                filename: "input".to_owned()
+            }
+        );
+    }
+}
+
+#[test]
+fn directive_pairs() {
+    let vars = json!({
+        "t": true,
+        "f": false,
+        "xs": [1, 2],
+        "tag": "h1",
+        "s": "b",
+        "h": "<i>hi</i>",
+        "a": { "id": "z" }
+    });
+
+    let cases: Vec<(&str, Result<&str, &str>)> = vec![
+        // pl-if
+        (
+            r#"<p pl-if="t" pl-else-if="t">x</p>"#,
+            Err("an element can only have one of pl-if, pl-else-if and pl-else"),
+        ),
+        (
+            r#"<p pl-if="t" pl-else>x</p>"#,
+            Err("an element can only have one of pl-if, pl-else-if and pl-else"),
+        ),
+        (
+            r#"<p pl-if="t" pl-for="x in xs">{{x}}</p>"#,
+            Ok("<p>1</p><p>2</p>"),
+        ),
+        (r#"<p pl-if="t" pl-is="tag">x</p>"#, Ok("<h1>x</h1>")),
+        (
+            r#"<div pl-if="t" pl-switch="s"><i pl-case="'a'">a</i><i pl-case="'b'">b</i></div>"#,
+            Ok("<div><i>b</i></div>"),
+        ),
+        (r#"<p pl-if="t" pl-text="s"></p>"#, Ok("<p>b</p>")),
+        (r#"<p pl-if="t" pl-html="h"></p>"#, Ok("<p><i>hi</i></p>")),
+        (r#"<p pl-if="t" pl-attrs="a">x</p>"#, Ok("<p id='z'>x</p>")),
+        (r#"<slot pl-if="t" pl-slot>fallback</slot>"#, Ok("fallback")),
+        // pl-else-if
+        (
+            r#"<p pl-if="f">a</p><p pl-else-if="t" pl-else>x</p>"#,
+            Err("an element can only have one of pl-if, pl-else-if and pl-else"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><p pl-else-if="t" pl-for="x in xs">{{x}}</p>"#,
+            Ok("<p>1</p><p>2</p>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><p pl-else-if="t" pl-is="tag">x</p>"#,
+            Ok("<h1>x</h1>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><div pl-else-if="t" pl-switch="s"><i pl-case="'b'">b</i></div>"#,
+            Ok("<div><i>b</i></div>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><p pl-else-if="t" pl-text="s"></p>"#,
+            Ok("<p>b</p>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><p pl-else-if="t" pl-html="h"></p>"#,
+            Ok("<p><i>hi</i></p>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><p pl-else-if="t" pl-attrs="a">x</p>"#,
+            Ok("<p id='z'>x</p>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><slot pl-else-if="t" pl-slot>fallback</slot>"#,
+            Ok("fallback"),
+        ),
+        // pl-else
+        (
+            r#"<p pl-if="f">a</p><p pl-else pl-for="x in xs">{{x}}</p>"#,
+            Ok("<p>1</p><p>2</p>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><p pl-else pl-is="tag">x</p>"#,
+            Ok("<h1>x</h1>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><div pl-else pl-switch="s"><i pl-case="'b'">b</i></div>"#,
+            Ok("<div><i>b</i></div>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><p pl-else pl-text="s"></p>"#,
+            Ok("<p>b</p>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><p pl-else pl-html="h"></p>"#,
+            Ok("<p><i>hi</i></p>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><p pl-else pl-attrs="a">x</p>"#,
+            Ok("<p id='z'>x</p>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><slot pl-else pl-slot>fallback</slot>"#,
+            Ok("fallback"),
+        ),
+        // pl-for
+        (
+            r#"<p pl-for="x in xs" pl-is="tag">{{x}}</p>"#,
+            Ok("<h1>1</h1><h1>2</h1>"),
+        ),
+        (
+            r#"<div pl-for="x in xs" pl-switch="x"><i pl-case="1">one</i><i pl-default>other</i></div>"#,
+            Ok("<div><i>one</i></div><div><i>other</i></div>"),
+        ),
+        (
+            r#"<p pl-for="x in xs" pl-text="x"></p>"#,
+            Ok("<p>1</p><p>2</p>"),
+        ),
+        (
+            r#"<p pl-for="x in xs" pl-html="h"></p>"#,
+            Ok("<p><i>hi</i></p><p><i>hi</i></p>"),
+        ),
+        (
+            r#"<p pl-for="x in xs" pl-attrs="{'id': x}"></p>"#,
+            Ok("<p id='1'></p><p id='2'></p>"),
+        ),
+        (r#"<slot pl-for="x in xs" pl-slot>{{x}}</slot>"#, Ok("12")),
+        // pl-is
+        (
+            r#"<div pl-is="tag" pl-switch="s"><i pl-case="'b'">b</i></div>"#,
+            Ok("<h1><i>b</i></h1>"),
+        ),
+        (r#"<p pl-is="tag" pl-text="s"></p>"#, Ok("<h1>b</h1>")),
+        (
+            r#"<p pl-is="tag" pl-html="h"></p>"#,
+            Ok("<h1><i>hi</i></h1>"),
+        ),
+        (
+            r#"<p pl-is="tag" pl-attrs="a">x</p>"#,
+            Ok("<h1 id='z'>x</h1>"),
+        ),
+        (
+            r#"<slot pl-is="tag" pl-slot>x</slot>"#,
+            Err("pl-is has no effect alongside pl-slot"),
+        ),
+        // pl-switch
+        (
+            r#"<div pl-switch="s" pl-text="s"><i pl-case="'b'">b</i></div>"#,
+            Err("pl-switch and pl-text can't be used on the same element"),
+        ),
+        (
+            r#"<div pl-switch="s" pl-html="h"><i pl-case="'b'">b</i></div>"#,
+            Err("pl-switch and pl-html can't be used on the same element"),
+        ),
+        (
+            r#"<div pl-switch="s" pl-attrs="a"><i pl-case="'b'">b</i></div>"#,
+            Ok("<div id='z'><i>b</i></div>"),
+        ),
+        (
+            r#"<slot pl-switch="s" pl-slot><i pl-case="'b'">b</i></slot>"#,
+            Err("pl-switch and pl-slot can't be used on the same element"),
+        ),
+        // pl-text
+        (
+            r#"<p pl-text="s" pl-html="h"></p>"#,
+            Err("pl-text and pl-html can't be used on the same element"),
+        ),
+        (r#"<p pl-text="s" pl-attrs="a"></p>"#, Ok("<p id='z'>b</p>")),
+        (
+            r#"<slot pl-text="s" pl-slot></slot>"#,
+            Err("pl-text and pl-slot can't be used on the same element"),
+        ),
+        // pl-html
+        (
+            r#"<p pl-html="h" pl-attrs="a"></p>"#,
+            Ok("<p id='z'><i>hi</i></p>"),
+        ),
+        (
+            r#"<slot pl-html="h" pl-slot></slot>"#,
+            Err("pl-html and pl-slot can't be used on the same element"),
+        ),
+        // pl-attrs
+        (
+            r#"<slot pl-attrs="a" pl-slot></slot>"#,
+            Err("pl-attrs has no effect alongside pl-slot"),
+        ),
+        // pl-case
+        (
+            r#"<div pl-switch="s"><i pl-case="'b'" pl-if="t">b</i></div>"#,
+            Err("pl-if can't be used on a pl-case or pl-default, add the condition to the pl-case instead"),
+        ),
+        (
+            r#"<div pl-switch="s"><i pl-case="'b'" pl-else-if="t">b</i></div>"#,
+            Err("pl-else-if can't be used on a pl-case or pl-default, add the condition to the pl-case instead"),
+        ),
+        (
+            r#"<div pl-switch="s"><i pl-case="'b'" pl-else>b</i></div>"#,
+            Err("pl-else can't be used on a pl-case or pl-default, add the condition to the pl-case instead"),
+        ),
+        (
+            r#"<div pl-switch="s"><i pl-case="'b'" pl-for="x in xs">{{x}}</i></div>"#,
+            Ok("<div><i>1</i><i>2</i></div>"),
+        ),
+        (
+            r#"<div pl-switch="1"><i pl-case="x" pl-for="x in xs">{{x}}</i></div>"#,
+            Err("pl-case is checked before pl-for, so it can't use the loop variable \"x\", try a pl-switch inside the loop instead"),
+        ),
+        (
+            r#"<div pl-switch="s"><i pl-case="'b'" pl-is="tag">b</i></div>"#,
+            Ok("<div><h1>b</h1></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><p pl-case="'b'" pl-switch="s"><i pl-case="'b'">b</i></p></div>"#,
+            Ok("<div><p><i>b</i></p></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><p pl-case="'b'" pl-text="s"></p></div>"#,
+            Ok("<div><p>b</p></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><p pl-case="'b'" pl-html="h"></p></div>"#,
+            Ok("<div><p><i>hi</i></p></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><p pl-case="'b'" pl-attrs="a">b</p></div>"#,
+            Ok("<div><p id='z'>b</p></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><slot pl-case="'b'" pl-slot>fallback</slot></div>"#,
+            Ok("<div>fallback</div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><p pl-case="'b'" pl-default>b</p></div>"#,
+            Err("pl-case and pl-default can't be used on the same element"),
+        ),
+        (
+            r#"<div pl-switch="s"><p pl-case="'b'" pl-raw>{{b}}</p></div>"#,
+            Ok("<div><p>{{b}}</p></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><slot pl-case="'b'" pl-use="c" ^n="1"></slot></div>"#,
+            Ok("<div><b>1</b></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><slot pl-case="'b'" pl-use="c" pl-fallthrough id="z" ^n="1"></slot></div>"#,
+            Ok("<div><b id='z'>1</b></div>"),
+        ),
+        // pl-default
+        (
+            r#"<div pl-switch="s"><i pl-default pl-if="t">b</i></div>"#,
+            Err("pl-if can't be used on a pl-case or pl-default, add the condition to the pl-case instead"),
+        ),
+        (
+            r#"<div pl-switch="s"><i pl-default pl-else-if="t">b</i></div>"#,
+            Err("pl-else-if can't be used on a pl-case or pl-default, add the condition to the pl-case instead"),
+        ),
+        (
+            r#"<div pl-switch="s"><i pl-default pl-else>b</i></div>"#,
+            Err("pl-else can't be used on a pl-case or pl-default, add the condition to the pl-case instead"),
+        ),
+        (
+            r#"<div pl-switch="s"><i pl-default pl-for="x in xs">{{x}}</i></div>"#,
+            Ok("<div><i>1</i><i>2</i></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><i pl-default pl-is="tag">b</i></div>"#,
+            Ok("<div><h1>b</h1></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><p pl-default pl-switch="s"><i pl-case="'b'">b</i></p></div>"#,
+            Ok("<div><p><i>b</i></p></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><p pl-default pl-text="s"></p></div>"#,
+            Ok("<div><p>b</p></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><p pl-default pl-html="h"></p></div>"#,
+            Ok("<div><p><i>hi</i></p></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><p pl-default pl-attrs="a">b</p></div>"#,
+            Ok("<div><p id='z'>b</p></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><slot pl-default pl-slot>fallback</slot></div>"#,
+            Ok("<div>fallback</div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><p pl-default pl-raw>{{b}}</p></div>"#,
+            Ok("<div><p>{{b}}</p></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><slot pl-default pl-use="c" ^n="1"></slot></div>"#,
+            Ok("<div><b>1</b></div>"),
+        ),
+        (
+            r#"<div pl-switch="s"><slot pl-default pl-use="c" pl-fallthrough id="z" ^n="1"></slot></div>"#,
+            Ok("<div><b id='z'>1</b></div>"),
+        ),
+        // pl-raw
+        (r#"<p pl-raw pl-if="t">{{x}}</p>"#, Ok("<p>{{x}}</p>")),
+        (
+            r#"<p pl-if="f">a</p><p pl-raw pl-else-if="t">{{x}}</p>"#,
+            Ok("<p>{{x}}</p>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><p pl-raw pl-else>{{x}}</p>"#,
+            Ok("<p>{{x}}</p>"),
+        ),
+        (
+            r#"<p pl-raw pl-for="x in xs">{{x}}</p>"#,
+            Ok("<p>{{x}}</p><p>{{x}}</p>"),
+        ),
+        (
+            r#"<p pl-raw pl-is="tag">x</p>"#,
+            Err("pl-is has no effect alongside pl-raw"),
+        ),
+        (
+            r#"<div pl-raw pl-switch="s"><i pl-case="'b'">b</i></div>"#,
+            Err("pl-switch has no effect alongside pl-raw"),
+        ),
+        (
+            r#"<p pl-raw pl-text="s"></p>"#,
+            Err("pl-text has no effect alongside pl-raw"),
+        ),
+        (
+            r#"<p pl-raw pl-html="h"></p>"#,
+            Err("pl-html has no effect alongside pl-raw"),
+        ),
+        (
+            r#"<p pl-raw pl-attrs="a"></p>"#,
+            Err("pl-attrs has no effect alongside pl-raw"),
+        ),
+        (
+            r#"<slot pl-raw pl-slot></slot>"#,
+            Err("pl-slot has no effect alongside pl-raw"),
+        ),
+        (
+            r#"<slot pl-raw pl-use="c"></slot>"#,
+            Err("pl-use has no effect alongside pl-raw"),
+        ),
+        (
+            r#"<slot pl-raw pl-use="c" pl-fallthrough></slot>"#,
+            Err("pl-use has no effect alongside pl-raw"),
+        ),
+        // pl-use
+        (r#"<slot pl-use="c" pl-if="t" ^n="1"></slot>"#, Ok("<b>1</b>")),
+        (
+            r#"<p pl-if="f">a</p><slot pl-use="c" pl-else-if="t" ^n="1"></slot>"#,
+            Ok("<b>1</b>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><slot pl-use="c" pl-else ^n="1"></slot>"#,
+            Ok("<b>1</b>"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-for="x in xs" ^n="x"></slot>"#,
+            Ok("<b>1</b><b>2</b>"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-is="tag" ^n="1"></slot>"#,
+            Err("pl-is has no effect alongside pl-use"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-switch="s" ^n="1"></slot>"#,
+            Err("pl-switch and pl-use can't be used on the same element"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-text="s" ^n="1"></slot>"#,
+            Err("pl-text and pl-use can't be used on the same element"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-html="h" ^n="1"></slot>"#,
+            Err("pl-html and pl-use can't be used on the same element"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-attrs="a" ^n="1"></slot>"#,
+            Err("pl-attrs has no effect alongside pl-use"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-slot ^n="1"></slot>"#,
+            Err("pl-use and pl-slot can't be used on the same element"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-fallthrough id="z" ^n="1"></slot>"#,
+            Ok("<b id='z'>1</b>"),
+        ),
+        // pl-fallthrough, which only works on a component
+        (
+            r#"<p pl-fallthrough pl-if="t">x</p>"#,
+            Err("pl-fallthrough only works on a component"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-fallthrough pl-if="t" id="z" ^n="1"></slot>"#,
+            Ok("<b id='z'>1</b>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><slot pl-use="c" pl-fallthrough pl-else-if="t" id="z" ^n="1"></slot>"#,
+            Ok("<b id='z'>1</b>"),
+        ),
+        (
+            r#"<p pl-if="f">a</p><slot pl-use="c" pl-fallthrough pl-else id="z" ^n="1"></slot>"#,
+            Ok("<b id='z'>1</b>"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-fallthrough pl-for="x in xs" id="z" ^n="x"></slot>"#,
+            Ok("<b id='z'>1</b><b id='z'>2</b>"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-fallthrough pl-is="tag" ^n="1"></slot>"#,
+            Err("pl-is has no effect alongside pl-use"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-fallthrough pl-switch="s" ^n="1"></slot>"#,
+            Err("pl-switch and pl-use can't be used on the same element"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-fallthrough pl-text="s" ^n="1"></slot>"#,
+            Err("pl-text and pl-use can't be used on the same element"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-fallthrough pl-html="h" ^n="1"></slot>"#,
+            Err("pl-html and pl-use can't be used on the same element"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-fallthrough pl-attrs="a" ^n="1"></slot>"#,
+            Err("pl-attrs has no effect alongside pl-use"),
+        ),
+        (
+            r#"<slot pl-use="c" pl-fallthrough pl-slot ^n="1"></slot>"#,
+            Err("pl-use and pl-slot can't be used on the same element"),
+        ),
+    ];
+
+    // the component used by `pl-use`
+    let define =
+        r#"<template pl-define="c"><template pl-prop="n"></template><b>{{n}}</b></template>"#;
+
+    for (template, expected) in cases {
+        let result = render(format!("{}{}", define, template), &vars);
+
+        match expected {
+            Ok(html) => assert_eq!(result, Ok(html.to_owned()), "{}", template),
+            Err(message) => assert_eq!(
+                result,
+                Err(RenderError {
+                    kind: RenderErrorKind::IllegalDirective(message.into()),
+                    filename: "input".to_owned()
+                }),
+                "{}",
+                template
+            ),
+        }
+    }
+}
+
+#[test]
+fn pl_if_with_loop_variable() {
+    let vars = json!({ "items": [{ "visible": true }, { "visible": false }] });
+
+    for template in [
+        r#"<p pl-if="item.visible" pl-for="item in items">x</p>"#,
+        r#"<p pl-if="loop.first" pl-for="item in items">x</p>"#,
+    ] {
+        let result = render(template.into(), &vars);
+
+        match result {
+            Err(RenderError {
+                kind: RenderErrorKind::IllegalDirective(message),
+                ..
+            }) => assert!(message.starts_with("pl-if is checked before pl-for")),
+            _ => panic!("expected an error, got {:?}", result),
+        }
+    }
+
+    let result = render(
+        r#"<p pl-for="item in items if item.visible">x</p>"#.into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<p>x</p>");
+
+    // `loop` is the enclosing loop's
+    let result = render(
+        r#"<div pl-for="x in [[1], [2, 3]]"><p pl-if="loop.last" pl-for="y in x">{{y}}</p></div>"#
+            .into(),
+        &vars,
+    );
+    assert_eq!(result.unwrap(), "<div></div><div><p>2</p><p>3</p></div>");
+}
+
+#[test]
+fn pl_raw() {
+    let vars = json!({ "show": true, "xs": [1, 2] });
+
+    let result = render(
+        r#"<div pl-raw ^class="x"><p pl-if="open" :class="c">{{ message }}</p></div><template pl-if="show" pl-raw>{{ a }}</template><i pl-for="x in xs" pl-raw>{{x}}</i>"#
+            .into(),
+        &vars,
+    );
+
+    assert_eq!(
+        result.unwrap(),
+        "<div ^class='x'><p pl-if='open' :class='c'>{{ message }}</p></div>{{ a }}<i>{{x}}</i><i>{{x}}</i>"
+    );
+}
+
+#[test]
+fn pl_raw_with_other_directive() {
+    let vars = json!({ "s": "a" });
+
+    let result = render(r#"<p pl-raw pl-text="s"></p>"#.into(), &vars);
+
     assert_eq!(
         result.unwrap_err(),
         RenderError {
-            kind: RenderErrorKind::TextRender(text_node::RenderError(Type::Null)),
+            kind: RenderErrorKind::IllegalDirective(
+                "pl-text has no effect alongside pl-raw".into()
+            ),
             filename: "input".to_owned()
         }
     );
 }
+
+#[test]
+fn escaped_text_node() {
+    let vars = json!({ "x": 1 });
+
+    let result = render(r#"<p>\{{ x }} is {{ x }}</p>"#.into(), &vars);
+
+    assert_eq!(result.unwrap(), "<p>{{ x }} is 1</p>");
+}